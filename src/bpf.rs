@@ -4,20 +4,26 @@ include!(concat!(env!("OUT_DIR"), "/hid_bpf_bindings.rs"));
 include!(concat!(env!("OUT_DIR"), "/attach.skel.rs"));
 
 use crate::hidudev;
+use crate::modalias;
 use errno;
 use libbpf_rs::skel::{OpenSkel, SkelBuilder};
+use serde::Serialize;
 use std::convert::TryInto;
 use std::fs;
-use std::os::fd::{AsFd, AsRawFd};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
 use std::path::PathBuf;
 
+pub static BPFFS_HID_DIR: &str = "/sys/fs/bpf/hid";
+
 pub struct HidBPF<'a> {
     inner: Option<AttachSkel<'a>>,
 }
 
 pub fn get_bpffs_path(sysname: &str, object: &str) -> String {
     format!(
-        "/sys/fs/bpf/hid/{}/{}",
+        "{}/{}/{}",
+        BPFFS_HID_DIR,
         sysname.replace(":", "_").replace(".", "_"),
         object.replace(":", "_").replace(".", "_"),
     )
@@ -31,6 +37,623 @@ pub fn remove_bpf_objects(sysname: &str) -> std::io::Result<()> {
     Ok(())
 }
 
+/// Whether any object is attached to the device, i.e. has its programs
+/// pinned in a directory of the device
+pub fn has_bpf_objects(sysname: &str) -> bool {
+    fs::read_dir(get_bpffs_path(sysname, ""))
+        .map(|mut entries| entries.any(|entry| entry.is_ok_and(|entry| entry.path().is_dir())))
+        .unwrap_or(false)
+}
+
+/// Removes the pins of a single object of a device, which detaches its programs
+pub fn remove_bpf_object(sysname: &str, object: &std::path::Path) -> std::io::Result<()> {
+    let object_name = object.file_stem().unwrap_or_default().to_string_lossy();
+
+    let result = std::fs::remove_dir_all(get_bpffs_path(sysname, &object_name));
+    remove_unused_device_dir(sysname);
+
+    result
+}
+
+/// Removes the folder of a device, and the copy of its original report
+/// descriptor, once no object is attached to it anymore
+fn remove_unused_device_dir(sysname: &str) {
+    if !has_bpf_objects(sysname) {
+        std::fs::remove_dir_all(get_bpffs_path(sysname, "")).ok();
+    }
+}
+
+/// The map pinned next to the objects of a device that keeps its report
+/// descriptor as it was before any rdesc_fixup program got attached. Once
+/// the device is bound, sysfs, hidraw and debugfs only expose the fixed one,
+/// and USB only gives the original one when no driver holds the interface.
+const ORIGINAL_RDESC_MAP: &str = "original_rdesc";
+const ORIGINAL_RDESC_MAX_SIZE: usize = 4096;
+
+/// Saves the report descriptor of a device before an rdesc_fixup program
+/// changes it. Only the first copy is kept, later ones are already fixed.
+fn save_original_rdesc(sysname: &str, rdesc: &[u8]) -> Result<(), libbpf_rs::Error> {
+    let path = get_bpffs_path(sysname, ORIGINAL_RDESC_MAP);
+
+    if std::path::Path::new(&path).exists() {
+        return Ok(());
+    }
+
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+    let mut map = libbpf_rs::MapHandle::create(
+        libbpf_rs::MapType::Array,
+        Some(ORIGINAL_RDESC_MAP),
+        4,
+        (4 + ORIGINAL_RDESC_MAX_SIZE) as u32,
+        1,
+        &opts,
+    )?;
+
+    /* the length first, then the report descriptor */
+    let length = rdesc.len().min(ORIGINAL_RDESC_MAX_SIZE);
+    let mut value = (length as u32).to_ne_bytes().to_vec();
+    value.extend_from_slice(&rdesc[..length]);
+    value.resize(4 + ORIGINAL_RDESC_MAX_SIZE, 0);
+
+    map.update(&0u32.to_ne_bytes(), &value, libbpf_rs::MapFlags::ANY)?;
+    map.pin(&path)
+}
+
+/// The report descriptor of a device before the rdesc_fixup programs
+/// attached by udev-hid-bpf, if any
+pub fn original_rdesc(sysname: &str) -> Result<Option<Vec<u8>>, libbpf_rs::Error> {
+    let path = get_bpffs_path(sysname, ORIGINAL_RDESC_MAP);
+
+    if !std::path::Path::new(&path).exists() {
+        return Ok(None);
+    }
+
+    let map = libbpf_rs::MapHandle::from_pinned_path(&path)?;
+    let value = match map.lookup(&0u32.to_ne_bytes(), libbpf_rs::MapFlags::ANY)? {
+        Some(value) if value.len() >= 4 => value,
+        _ => return Ok(None),
+    };
+    let length = u32::from_ne_bytes(value[..4].try_into().unwrap()) as usize;
+
+    Ok(value.get(4..4 + length).map(|rdesc| rdesc.to_vec()))
+}
+
+/// The map pinned next to the programs of an object that keeps its
+/// priority, only when not the default one
+const PRIORITY_MAP: &str = "hid_bpf_priority";
+
+/// The flag of hid_bpf_attach_prog() putting the program first
+const HID_BPF_FLAG_INSERT_HEAD: u32 = 1;
+
+fn save_priority(object_dir: &str, priority: i32) -> Result<(), libbpf_rs::Error> {
+    let opts = libbpf_sys::bpf_map_create_opts {
+        sz: std::mem::size_of::<libbpf_sys::bpf_map_create_opts>() as libbpf_sys::size_t,
+        ..Default::default()
+    };
+    let mut map = libbpf_rs::MapHandle::create(
+        libbpf_rs::MapType::Array,
+        Some(PRIORITY_MAP),
+        4,
+        4,
+        1,
+        &opts,
+    )?;
+
+    map.update(
+        &0u32.to_ne_bytes(),
+        &priority.to_ne_bytes(),
+        libbpf_rs::MapFlags::ANY,
+    )?;
+    map.pin(format!("{}/{}", object_dir, PRIORITY_MAP))
+}
+
+/// The priority of an attached object, 0 if none was saved
+fn pinned_priority(object_dir: &std::path::Path) -> i32 {
+    let priority = libbpf_rs::MapHandle::from_pinned_path(object_dir.join(PRIORITY_MAP))
+        .and_then(|map| map.lookup(&0u32.to_ne_bytes(), libbpf_rs::MapFlags::ANY));
+
+    match priority {
+        Ok(Some(value)) if value.len() == 4 => i32::from_ne_bytes(value[..].try_into().unwrap()),
+        _ => 0,
+    }
+}
+
+/// A program attached to a device by another object
+struct AttachedProgram {
+    /// the pin of its link
+    path: std::path::PathBuf,
+    /// the name of the folder of its object
+    object: String,
+    priority: i32,
+    link_id: u32,
+    prog: OwnedFd,
+    /// the kernel function it is attached to, e.g. hid_bpf_device_event
+    attach_btf_id: u32,
+}
+
+/// The kernel function a tracing program is attached to. The kernel runs the
+/// programs attached to the same function one after the other.
+fn prog_attach_btf_id(prog: std::os::fd::BorrowedFd) -> Option<u32> {
+    let mut info = libbpf_sys::bpf_prog_info::default();
+    let mut len = std::mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+    let info_ptr: *mut libc::c_void = &mut info as *mut _ as *mut libc::c_void;
+
+    if unsafe { libbpf_sys::bpf_obj_get_info_by_fd(prog.as_raw_fd(), info_ptr, &mut len) } != 0 {
+        return None;
+    }
+
+    Some(info.attach_btf_id)
+}
+
+/// The programs attached to a device by the objects but `object_name`, in
+/// the order they are run
+fn attached_programs(sysname: &str, object_name: &str) -> Vec<AttachedProgram> {
+    let object_dir = get_bpffs_path(sysname, object_name);
+    let mut programs = Vec::new();
+
+    let objects = fs::read_dir(get_bpffs_path(sysname, ""))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && path.as_path() != std::path::Path::new(&object_dir));
+
+    for object in objects {
+        let priority = pinned_priority(&object);
+
+        for pin in fs::read_dir(&object).into_iter().flatten().flatten() {
+            /* maps have no prog_id */
+            let fdinfo = pinned_fdinfo(&pin.path()).unwrap_or_default();
+            let id = |key: &str| fdinfo.get(key).and_then(|v| v.parse::<u32>().ok());
+            let (prog_id, link_id) = match (id("prog_id"), id("link_id")) {
+                (Some(prog_id), Some(link_id)) => (prog_id, link_id),
+                _ => continue,
+            };
+
+            let fd = unsafe { libbpf_sys::bpf_prog_get_fd_by_id(prog_id) };
+            if fd < 0 {
+                continue;
+            }
+            let prog = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            if let Some(attach_btf_id) = prog_attach_btf_id(prog.as_fd()) {
+                programs.push(AttachedProgram {
+                    path: pin.path(),
+                    object: object.file_name().unwrap().to_string_lossy().to_string(),
+                    priority,
+                    link_id,
+                    prog,
+                    attach_btf_id,
+                });
+            }
+        }
+    }
+
+    programs.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then(a.object.cmp(&b.object))
+            .then(a.link_id.cmp(&b.link_id))
+    });
+
+    programs
+}
+
+/// The priority an object declares in its metadata, 0 by default
+pub fn object_priority(path: &std::path::Path) -> i32 {
+    libbpf_rs::btf::Btf::from_path(path)
+        .map(|btf| modalias::ObjectOptions::from_btf(&btf).priority)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModaliasInfo {
+    pub bus: usize,
+    pub group: usize,
+    pub vid: u32,
+    pub pid: u32,
+    pub name: Option<String>,
+    pub uniq: Option<String>,
+    pub phys: Option<String>,
+    pub rdesc_size_min: Option<usize>,
+    pub rdesc_size_max: Option<usize>,
+    pub rdesc_sha256: Option<String>,
+    pub usage_page: Option<u32>,
+    pub usage: Option<u32>,
+    pub modalias: String,
+    pub hid_device: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProgramInfo {
+    pub name: String,
+    pub section: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ObjectInfo {
+    pub path: String,
+    pub modaliases: Vec<ModaliasInfo>,
+    pub programs: Vec<ProgramInfo>,
+    /// the maps that get pinned in the bpffs when the object is attached
+    pub maps: Vec<String>,
+    /// whether the programs are kept when one of them fails to attach
+    pub programs_independent: bool,
+    pub priority: i32,
+}
+
+/// Parses the HID_BPF_CONFIG metadata of a BPF object
+pub fn object_modaliases(path: &std::path::Path) -> Result<Vec<ModaliasInfo>, libbpf_rs::Error> {
+    let btf = libbpf_rs::btf::Btf::from_path(path)?;
+    let mut modaliases = Vec::new();
+
+    if let Some(metadata) = modalias::Metadata::from_btf(&btf) {
+        for modalias in metadata.modaliases() {
+            modaliases.push(ModaliasInfo {
+                bus: usize::from(&modalias.bus),
+                group: usize::from(&modalias.group),
+                vid: modalias.vid,
+                pid: modalias.pid,
+                name: modalias.name.clone(),
+                uniq: modalias.uniq.clone(),
+                phys: modalias.phys.clone(),
+                rdesc_size_min: modalias.rdesc_size_min,
+                rdesc_size_max: modalias.rdesc_size_max,
+                rdesc_sha256: modalias.rdesc_sha256.clone(),
+                usage_page: modalias.usage_page,
+                usage: modalias.usage,
+                hid_device: modalias.hid_device_entry(),
+                modalias: String::from(modalias),
+            });
+        }
+    }
+
+    Ok(modaliases)
+}
+
+/// Returns the metadata entries of a BPF object
+pub fn object_metadata(
+    path: &std::path::Path,
+) -> Result<Vec<modalias::Modalias>, libbpf_rs::Error> {
+    let btf = libbpf_rs::btf::Btf::from_path(path)?;
+
+    Ok(match modalias::Metadata::from_btf(&btf) {
+        Some(metadata) => metadata.modaliases().collect(),
+        None => Vec::new(),
+    })
+}
+
+/// Returns all BPF objects in `bpf_dir` with the metadata they carry
+pub fn objects_metadata(
+    bpf_dir: &std::path::Path,
+) -> std::io::Result<Vec<(PathBuf, Vec<modalias::Modalias>)>> {
+    let mut objects = Vec::new();
+
+    if !bpf_dir.exists() {
+        return Ok(objects);
+    }
+
+    for entry in fs::read_dir(bpf_dir)?.flatten() {
+        let path = entry.path();
+        if !path.to_string_lossy().ends_with(".bpf.o") {
+            continue;
+        }
+
+        let modaliases = object_metadata(&path).unwrap_or_default();
+        objects.push((path, modaliases));
+    }
+
+    objects.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Ok(objects)
+}
+
+/// Gather the metadata, programs and maps of a BPF object without loading it
+pub fn inspect_object(path: &std::path::Path) -> Result<ObjectInfo, libbpf_rs::Error> {
+    let modaliases = object_modaliases(path)?;
+    let options = modalias::ObjectOptions::from_btf(&libbpf_rs::btf::Btf::from_path(path)?);
+
+    let object = libbpf_rs::ObjectBuilder::default().open_file(path)?;
+
+    let programs = object
+        .progs_iter()
+        .map(|prog| ProgramInfo {
+            name: String::from(prog.name().unwrap_or("<invalid>")),
+            section: String::from(prog.section()),
+        })
+        .collect();
+
+    /* same filter as in load_programs(): skip the compiler internal maps */
+    let maps = object
+        .maps_iter()
+        .filter_map(|map| map.name().ok())
+        .filter(|name| !name.contains("."))
+        .map(String::from)
+        .collect();
+
+    Ok(ObjectInfo {
+        path: path.display().to_string(),
+        modaliases,
+        programs,
+        maps,
+        programs_independent: options.programs_independent,
+        priority: options.priority,
+    })
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifiedProgram {
+    pub name: String,
+    pub section: String,
+    /// the instructions of the program, as compiled
+    pub instructions: usize,
+    /// the instructions the verifier went through, from its statistics
+    pub processed: Option<u64>,
+    pub accepted: bool,
+    pub error: Option<String>,
+    pub log: String,
+}
+
+/// The libbpf messages printed while verify_object() loads a program
+static VERIFIER_MESSAGES: std::sync::Mutex<String> = std::sync::Mutex::new(String::new());
+
+fn capture_verifier_messages(_level: libbpf_rs::PrintLevel, msg: String) {
+    if let Ok(mut messages) = VERIFIER_MESSAGES.lock() {
+        messages.push_str(&msg);
+    }
+}
+
+/// Extracts the verifier log from the libbpf messages of a program load
+fn verifier_log(messages: &str) -> String {
+    const BEGIN: &str = "-- BEGIN PROG LOAD LOG --";
+    const END: &str = "-- END PROG LOAD LOG --";
+
+    match (messages.find(BEGIN), messages.find(END)) {
+        (Some(begin), Some(end)) if begin < end => messages[begin + BEGIN.len()..end]
+            .trim_matches('\n')
+            .to_string(),
+        _ => messages.trim().to_string(),
+    }
+}
+
+/// Parses the "processed N insns" statistics line of a verifier log
+fn verifier_processed_insns(log: &str) -> Option<u64> {
+    log.lines()
+        .filter_map(|line| line.strip_prefix("processed "))
+        .filter_map(|line| line.split_whitespace().next())
+        .find_map(|count| count.parse().ok())
+}
+
+/// Loads each program of the object on its own, without attaching it, and
+/// gathers the verifier log and statistics of each of them. A rejected
+/// program does not prevent the following ones from being verified.
+pub fn verify_object(path: &std::path::Path) -> Result<Vec<VerifiedProgram>, libbpf_rs::Error> {
+    let names: Vec<String> = libbpf_rs::ObjectBuilder::default()
+        .open_file(path)?
+        .progs_iter()
+        .filter_map(|prog| prog.name().ok().map(String::from))
+        .collect();
+    let mut programs = Vec::new();
+
+    for name in names {
+        let mut object = libbpf_rs::ObjectBuilder::default().open_file(path)?;
+        let mut section = String::new();
+        let mut instructions = 0;
+
+        for prog in object.progs_iter_mut() {
+            if prog.name().ok() == Some(name.as_str()) {
+                section = String::from(prog.section());
+                instructions = prog.insn_cnt();
+                /* the full log (1) and the statistics (4) */
+                prog.set_log_level(1 | 4)?;
+            } else {
+                prog.set_autoload(false)?;
+            }
+        }
+
+        VERIFIER_MESSAGES.lock().unwrap().clear();
+        let previous = libbpf_rs::set_print(Some((
+            libbpf_rs::PrintLevel::Debug,
+            capture_verifier_messages,
+        )));
+        let result = object.load();
+        libbpf_rs::set_print(previous);
+
+        let log = verifier_log(&VERIFIER_MESSAGES.lock().unwrap());
+        programs.push(VerifiedProgram {
+            name,
+            section,
+            instructions,
+            processed: verifier_processed_insns(&log),
+            accepted: result.is_ok(),
+            error: result.err().map(|e| e.to_string()),
+            log,
+        });
+    }
+
+    Ok(programs)
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinnedProgram {
+    pub name: String,
+    pub prog_id: Option<u32>,
+    pub link_id: Option<u32>,
+    /// UNIX timestamp of when the program was loaded
+    pub load_time: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinnedMap {
+    pub name: String,
+    pub map_id: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinnedObject {
+    pub name: String,
+    pub priority: i32,
+    pub programs: Vec<PinnedProgram>,
+    pub maps: Vec<PinnedMap>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PinnedDevice {
+    pub sysname: String,
+    pub bpffs_path: String,
+    /// false if the HID device is gone but its pins are still around
+    pub present: bool,
+    pub objects: Vec<PinnedObject>,
+}
+
+/// Reverts the mangling of get_bpffs_path(), 0003_04D9_A09F_0009 gives 0003:04D9:A09F.0009
+fn sysname_from_bpffs_name(name: &str) -> String {
+    let parts: Vec<&str> = name.split('_').collect();
+
+    match parts[..] {
+        [bus, vid, pid, id] => format!("{bus}:{vid}:{pid}.{id}"),
+        _ => String::from(name),
+    }
+}
+
+/// Parses /proc/self/fdinfo of the bpf object pinned at `path`
+fn pinned_fdinfo(path: &std::path::Path) -> Option<std::collections::HashMap<String, String>> {
+    let c_str = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let fd = unsafe { libbpf_sys::bpf_obj_get(c_str.as_ptr()) };
+    if fd < 0 {
+        return None;
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let fdinfo = fs::read_to_string(format!("/proc/self/fdinfo/{}", fd.as_raw_fd())).ok()?;
+
+    Some(
+        fdinfo
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (String::from(key.trim()), String::from(value.trim())))
+            .collect(),
+    )
+}
+
+fn prog_load_time(prog_id: u32) -> Option<u64> {
+    let fd = unsafe { libbpf_sys::bpf_prog_get_fd_by_id(prog_id) };
+    if fd < 0 {
+        return None;
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut info = libbpf_sys::bpf_prog_info::default();
+    let mut len = std::mem::size_of::<libbpf_sys::bpf_prog_info>() as u32;
+    let info_ptr: *mut libc::c_void = &mut info as *mut _ as *mut libc::c_void;
+
+    if unsafe { libbpf_sys::bpf_obj_get_info_by_fd(fd.as_raw_fd(), info_ptr, &mut len) } != 0 {
+        return None;
+    }
+
+    /* load_time is in ns since boot, turn it into a UNIX timestamp */
+    let mut boottime = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut boottime) };
+    let since_boot = boottime.tv_sec as u64 * 1_000_000_000 + boottime.tv_nsec as u64;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_nanos() as u64;
+
+    Some((now - since_boot + info.load_time) / 1_000_000_000)
+}
+
+fn pinned_object(path: &std::path::Path) -> std::io::Result<PinnedObject> {
+    let mut programs = Vec::new();
+    let mut maps = Vec::new();
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == PRIORITY_MAP {
+            continue;
+        }
+
+        let fdinfo = pinned_fdinfo(&entry.path()).unwrap_or_default();
+        let id = |key: &str| fdinfo.get(key).and_then(|v| v.parse::<u32>().ok());
+
+        /* maps are pinned directly, programs are pinned through their HID-BPF link */
+        if fdinfo.contains_key("map_id") {
+            maps.push(PinnedMap {
+                name,
+                map_id: id("map_id"),
+            });
+        } else {
+            let prog_id = id("prog_id");
+            programs.push(PinnedProgram {
+                name,
+                prog_id,
+                link_id: id("link_id"),
+                load_time: prog_id.and_then(prog_load_time),
+            });
+        }
+    }
+
+    programs.sort_by(|a, b| a.name.cmp(&b.name));
+    maps.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(PinnedObject {
+        name: path.file_name().unwrap().to_string_lossy().to_string(),
+        priority: pinned_priority(path),
+        programs,
+        maps,
+    })
+}
+
+/// Walks the bpffs and returns what is currently pinned for each HID device
+pub fn pinned_devices() -> std::io::Result<Vec<PinnedDevice>> {
+    let mut devices = Vec::new();
+    let root = std::path::Path::new(BPFFS_HID_DIR);
+
+    if !root.exists() {
+        return Ok(devices);
+    }
+
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        if !entry.path().is_dir() {
+            continue;
+        }
+
+        let sysname = sysname_from_bpffs_name(&entry.file_name().to_string_lossy());
+        let present = std::path::Path::new("/sys/bus/hid/devices")
+            .join(&sysname)
+            .exists();
+
+        let mut objects = Vec::new();
+        for object in fs::read_dir(entry.path())? {
+            let object = object?;
+            if object.path().is_dir() {
+                objects.push(pinned_object(&object.path())?);
+            }
+        }
+        /* in the order they process the events */
+        objects.sort_by(|a, b| b.priority.cmp(&a.priority).then(a.name.cmp(&b.name)));
+
+        devices.push(PinnedDevice {
+            sysname,
+            bpffs_path: entry.path().display().to_string(),
+            present,
+            objects,
+        });
+    }
+
+    devices.sort_by(|a, b| a.sysname.cmp(&b.sysname));
+
+    Ok(devices)
+}
+
 fn run_syscall_prog<T>(prog: &libbpf_rs::Program, data: T) -> Result<T, libbpf_rs::Error> {
     let fd = prog.as_fd().as_raw_fd();
     let data_ptr: *const libc::c_void = &data as *const _ as *const libc::c_void;
@@ -63,23 +686,177 @@ fn pin_hid_bpf_prog(link: i32, path: String) -> Result<(), libbpf_rs::Error> {
     }
 }
 
+/// Copies a udev property into a fixed size C string of the probe arguments
+fn copy_c_string(dest: &mut [std::os::raw::c_char], value: Option<&String>) {
+    let bytes = value.map(|value| value.as_bytes()).unwrap_or_default();
+    /* keep the trailing NUL */
+    let length = bytes.len().min(dest.len() - 1);
+
+    for (dest, byte) in dest.iter_mut().zip(&bytes[..length]) {
+        *dest = *byte as std::os::raw::c_char;
+    }
+}
+
 impl hid_bpf_probe_args {
-    fn from(device: &hidudev::HidUdev) -> Self {
-        let syspath = device.syspath();
-        let rdesc = syspath + "/report_descriptor";
+    /// `rdesc_size` is always the size of the report descriptor, which is
+    /// truncated if it does not fit in `rdesc`. Probes can tell from
+    /// `rdesc_size > sizeof(ctx->rdesc)`. The device identity is left
+    /// empty, see [`hid_bpf_probe_args::from`].
+    fn new(hid: u32, rdesc: &[u8]) -> Self {
+        let mut args = hid_bpf_probe_args {
+            hid,
+            rdesc_size: rdesc.len() as u32,
+            rdesc: [0; 4096],
+            retval: -1,
+            version: HID_BPF_PROBE_ARGS_VERSION,
+            bus: 0,
+            group: 0,
+            vid: 0,
+            pid: 0,
+            usb_interface: -1,
+            name: [0; 128],
+            uniq: [0; 64],
+            phys: [0; 64],
+        };
+        let length = rdesc.len().min(args.rdesc.len());
 
-        let mut buffer = fs::read(&rdesc).unwrap();
-        let length = buffer.len();
+        if length < rdesc.len() {
+            log::warn!(
+                "report descriptor of device id {} is {} bytes, only the first {} are given to the probe",
+                hid,
+                rdesc.len(),
+                length,
+            );
+        }
 
-        buffer.resize(4096, 0);
+        args.rdesc[..length].copy_from_slice(&rdesc[..length]);
 
-        hid_bpf_probe_args {
-            hid: device.id(),
-            rdesc_size: length as u32,
-            rdesc: buffer.try_into().unwrap(),
-            retval: -1,
+        args
+    }
+
+    fn from(device: &hidudev::HidUdev) -> Result<Self, libbpf_rs::Error> {
+        let rdesc = device.report_descriptor().map_err(|e| {
+            libbpf_rs::Error::InvalidInput(format!(
+                "could not read the report descriptor of {}: {}",
+                device.sysname(),
+                e
+            ))
+        })?;
+        let modalias = device.modalias().map_err(|e| {
+            libbpf_rs::Error::InvalidInput(format!(
+                "could not read the modalias of {}: {}",
+                device.sysname(),
+                e
+            ))
+        })?;
+        let mut args = Self::new(device.id(), &rdesc);
+
+        args.bus = usize::from(&modalias.bus) as u32;
+        args.group = usize::from(&modalias.group) as u32;
+        args.vid = modalias.vid;
+        args.pid = modalias.pid;
+        args.usb_interface = device.usb_interface().map_or(-1, i32::from);
+        copy_c_string(&mut args.name, modalias.name.as_ref());
+        copy_c_string(&mut args.uniq, modalias.uniq.as_ref());
+        copy_c_string(&mut args.phys, modalias.phys.as_ref());
+
+        Ok(args)
+    }
+}
+
+/// Runs the "probe" syscall program of the object, if any, and returns its retval
+fn run_probe(
+    object: &libbpf_rs::Object,
+    args: hid_bpf_probe_args,
+) -> Result<Option<i32>, libbpf_rs::Error> {
+    match object.prog("probe") {
+        Some(probe) => {
+            let args = run_syscall_prog(probe, args)?;
+
+            Ok(Some(args.retval))
+        }
+        None => Ok(None),
+    }
+}
+
+/// A global variable of a loaded BPF object
+#[derive(Debug, Serialize)]
+pub struct GlobalVariable {
+    pub name: String,
+    pub section: String,
+    pub value: Vec<u8>,
+}
+
+/// Reads the global variables of a loaded object from its .bss and .data
+/// maps, e.g. the state its probe computed for the programs to attach
+fn global_variables(path: &std::path::Path, object: &libbpf_rs::Object) -> Vec<GlobalVariable> {
+    let btf = match libbpf_rs::btf::Btf::from_path(path) {
+        Ok(btf) => btf,
+        Err(_) => return Vec::new(),
+    };
+    let mut variables = Vec::new();
+
+    for section in [".bss", ".data"] {
+        let datasec = match btf.type_by_name::<libbpf_rs::btf::types::DataSec>(section) {
+            Some(datasec) => datasec,
+            None => continue,
+        };
+        /* libbpf names the maps after the object, e.g. "XBox_Eli.bss" */
+        let data = match object
+            .maps_iter()
+            .find(|map| map.name().ends_with(section))
+            .map(|map| map.lookup(&0u32.to_ne_bytes(), libbpf_rs::MapFlags::ANY))
+        {
+            Some(Ok(Some(data))) => data,
+            _ => continue,
+        };
+
+        for var_sec_info in datasec.iter() {
+            let name = btf
+                .type_by_id::<libbpf_rs::btf::types::Var>(var_sec_info.ty)
+                .and_then(|var| var.name())
+                .map(|name| name.to_string_lossy().to_string());
+            let start = var_sec_info.offset as usize;
+            let value = data.get(start..start + var_sec_info.size as usize);
+
+            if let (Some(name), Some(value)) = (name, value) {
+                variables.push(GlobalVariable {
+                    name,
+                    section: String::from(section),
+                    value: value.to_vec(),
+                });
+            }
         }
     }
+
+    variables
+}
+
+/// Loads the object at `path` and runs its probe against the device, without
+/// attaching anything. Returns `None` if the object has no probe.
+pub fn probe_object(
+    path: &PathBuf,
+    device: &hidudev::HidUdev,
+) -> Result<Option<i32>, libbpf_rs::Error> {
+    let mut obj_builder = libbpf_rs::ObjectBuilder::default();
+    let object = obj_builder.open_file(path.clone())?.load()?;
+
+    run_probe(&object, hid_bpf_probe_args::from(device)?)
+}
+
+/// Same as probe_object() but with a report descriptor that does not come
+/// from a real device, e.g. one saved in a file. Also returns the global
+/// variables of the object as the probe left them.
+pub fn probe_object_with_rdesc(
+    path: &PathBuf,
+    hid_id: u32,
+    rdesc: &[u8],
+) -> Result<Option<(i32, Vec<GlobalVariable>)>, libbpf_rs::Error> {
+    let mut obj_builder = libbpf_rs::ObjectBuilder::default();
+    let object = obj_builder.open_file(path.clone())?.load()?;
+
+    Ok(run_probe(&object, hid_bpf_probe_args::new(hid_id, rdesc))?
+        .map(|retval| (retval, global_variables(path, &object))))
 }
 
 impl<'a> HidBPF<'a> {
@@ -90,13 +867,106 @@ impl<'a> HidBPF<'a> {
         Ok(Self { inner })
     }
 
+    /// Attaches a tracing program to the device, closing the returned link
+    /// detaches it
+    fn attach(
+        &self,
+        prog: std::os::fd::BorrowedFd,
+        hid_id: u32,
+        flags: u32,
+    ) -> Result<OwnedFd, libbpf_rs::Error> {
+        let inner = self.inner.as_ref().expect("open_and_load() never called!");
+
+        let attach_args = AttachProgArgs {
+            prog_fd: prog.as_raw_fd(),
+            hid: hid_id,
+            flags,
+            retval: -1,
+        };
+
+        let args = run_syscall_prog(inner.progs().attach_prog(), attach_args)?;
+
+        if args.retval <= 0 {
+            return Err(libbpf_rs::Error::System(args.retval));
+        }
+
+        Ok(unsafe { OwnedFd::from_raw_fd(args.retval) })
+    }
+
+    /// Attaches a tracing program to the device and pins the resulting link
+    /// in `dir`. Returns the path of the pin, dropping it detaches the program.
+    fn attach_and_pin(
+        &self,
+        prog: &libbpf_rs::Program,
+        hid_id: u32,
+        flags: u32,
+        dir: &str,
+    ) -> Result<String, libbpf_rs::Error> {
+        /* the pin keeps the link, ours can be closed once done */
+        let link = self.attach(prog.as_fd(), hid_id, flags)?;
+
+        log::debug!(
+            target: "libbpf",
+            "successfully attached {} to device id {}",
+            prog.name(),
+            hid_id,
+        );
+
+        let path = format!("{}/{}", dir, prog.name());
+
+        fs::create_dir_all(dir).unwrap_or_else(|why| {
+            log::warn!("! {:?}", why.kind());
+        });
+
+        pin_hid_bpf_prog(link.as_raw_fd(), path.clone())?;
+
+        Ok(path)
+    }
+
+    /// Attaches the programs of other objects again, after the ones attached
+    /// so far. Their pins only move to the new links once all of them are
+    /// attached, so they stay where they were if one fails.
+    fn attach_again(
+        &self,
+        programs: &[&AttachedProgram],
+        hid_id: u32,
+    ) -> Result<(), libbpf_rs::Error> {
+        let mut links = Vec::new();
+
+        for program in programs {
+            links.push(self.attach(program.prog.as_fd(), hid_id, 0)?);
+        }
+
+        for (program, link) in programs.iter().zip(links) {
+            /* removing the pin detaches the previous link */
+            fs::remove_file(&program.path).ok();
+            pin_hid_bpf_prog(link.as_raw_fd(), program.path.to_string_lossy().to_string())?;
+            log::debug!(
+                target: "libbpf",
+                "attached {} again to device id {}",
+                program.path.display(),
+                hid_id,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Attaches the programs of the object at `path` to the device, with
+    /// `priority` instead of the one of its metadata if given
     pub fn load_programs(
         &self,
         path: &PathBuf,
         device: &hidudev::HidUdev,
+        priority: Option<i32>,
     ) -> Result<bool, libbpf_rs::Error> {
         log::debug!(target: "libbpf", "loading BPF object at {:?}", path.display());
 
+        /* whoever picked the object, its metadata must match the device */
+        if !device.metadata_matches(path) {
+            return Ok(false);
+        }
+
         let mut obj_builder = libbpf_rs::ObjectBuilder::default();
         let mut object = obj_builder.open_file(path.clone())?.load()?;
         let object_name = path.as_path().file_stem().unwrap().to_str().unwrap();
@@ -107,94 +977,187 @@ impl<'a> HidBPF<'a> {
          * if there is a "probe" syscall, execute it and
          * check for the return value: if not 0, then ignore
          * this bpf.o file
+         *
+         * The programs below are attached from this very same object, so
+         * whatever the probe wrote in the global variables (report ID,
+         * offsets in the report descriptor, ...) is what they get.
          */
-        if let Some(probe) = object.prog("probe") {
-            let args = hid_bpf_probe_args::from(device);
-
-            let args = run_syscall_prog(probe, args)?;
-
-            if args.retval != 0 {
+        if let Some(retval) = run_probe(&object, hid_bpf_probe_args::from(device)?)? {
+            if retval != 0 {
                 return Ok(false);
             }
-        };
-
-        let inner = self.inner.as_ref().expect("open_and_load() never called!");
-        let mut attached = false;
-
-        for prog in object.progs_iter() {
-            let tracing_prog = match prog.prog_type() {
-                libbpf_rs::ProgramType::Tracing => prog,
-                _ => continue,
-            };
-
-            let attach_args = AttachProgArgs {
-                prog_fd: tracing_prog.as_fd().as_raw_fd(),
-                hid: hid_id,
-                retval: -1,
-            };
-
-            let ret_syscall = run_syscall_prog(inner.progs().attach_prog(), attach_args);
 
-            if let Err(e) = ret_syscall {
-                log::warn!(
-                    "could not call attach {} to device id {}, error {}",
-                    &tracing_prog.name(),
-                    hid_id,
-                    e.to_string(),
-                );
-                continue;
+            if log::log_enabled!(target: "libbpf", log::Level::Debug) {
+                for variable in global_variables(path, &object) {
+                    log::debug!(
+                        target: "libbpf",
+                        "probe of {} set {} to {:02x?}",
+                        object_name,
+                        variable.name,
+                        variable.value,
+                    );
+                }
             }
+        };
 
-            let args = ret_syscall.unwrap();
+        /* keep the report descriptor as it is before we change it */
+        let original_rdesc = if object
+            .progs_iter()
+            .any(|prog| prog.section().ends_with("hid_bpf_rdesc_fixup"))
+        {
+            device.report_descriptor().ok()
+        } else {
+            None
+        };
 
-            if args.retval <= 0 {
-                log::warn!(
-                    "could not attach {} to device id {}, error {}",
-                    &tracing_prog.name(),
-                    hid_id,
-                    libbpf_rs::Error::System(args.retval).to_string(),
-                );
-                continue;
-            }
+        let options = libbpf_rs::btf::Btf::from_path(path)
+            .map(|btf| modalias::ObjectOptions::from_btf(&btf))
+            .unwrap_or_default();
+        let object_dir = get_bpffs_path(&device.sysname(), object_name);
+        let mut pinned = Vec::new();
+        let mut rdesc_fixup_attached = false;
 
-            let link = args.retval;
+        /*
+         * The kernel runs the programs attached to the same function
+         * (hid_bpf_device_event, hid_bpf_rdesc_fixup, ...) in the order they
+         * are attached, or puts them first with HID_BPF_FLAG_INSERT_HEAD.
+         * Objects with a higher priority (then a lower name) must come first:
+         * when this object goes between the attached ones, those that come
+         * after it are attached again once it is attached.
+         */
+        let priority = priority.unwrap_or(options.priority);
+        let object_pin_name = std::path::Path::new(&object_dir)
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let others = attached_programs(&device.sysname(), object_name);
+        let comes_after = |other: &AttachedProgram| {
+            other.priority < priority
+                || (other.priority == priority && other.object > object_pin_name)
+        };
+        let mut head = Vec::new();
+        let mut tail = Vec::new();
+        let mut moved_functions = Vec::new();
 
-            log::debug!(
-                target: "libbpf",
-                "successfully attached {} to device id {}",
-                &tracing_prog.name(),
-                hid_id,
-            );
+        for prog in object
+            .progs_iter()
+            .filter(|prog| matches!(prog.prog_type(), libbpf_rs::ProgramType::Tracing))
+        {
+            let attach_btf_id = prog_attach_btf_id(prog.as_fd());
+            let same: Vec<&AttachedProgram> = others
+                .iter()
+                .filter(|other| Some(other.attach_btf_id) == attach_btf_id)
+                .collect();
 
-            let path = format!(
-                "{}/{}",
-                get_bpffs_path(&device.sysname(), object_name),
-                tracing_prog.name(),
-            );
+            if !same.iter().any(|other| comes_after(other)) {
+                tail.push(prog);
+            } else if same.iter().all(|other| comes_after(other)) {
+                head.push(prog);
+            } else {
+                tail.push(prog);
+                moved_functions.extend(attach_btf_id);
+            }
+        }
 
-            fs::create_dir_all(get_bpffs_path(&device.sysname(), object_name)).unwrap_or_else(
-                |why| {
-                    log::warn!("! {:?}", why.kind());
-                },
-            );
+        /* inserting each at the head reverses them */
+        head.reverse();
+        let progs = head
+            .into_iter()
+            .map(|prog| (prog, HID_BPF_FLAG_INSERT_HEAD))
+            .chain(tail.into_iter().map(|prog| (prog, 0)));
 
-            match pin_hid_bpf_prog(link, path.clone()) {
+        for (prog, flags) in progs {
+            match self.attach_and_pin(prog, hid_id, flags, &object_dir) {
+                Ok(path) => {
+                    log::debug!(target: "libbpf", "Successfully pinned prog at {}", path);
+                    pinned.push(path);
+                    rdesc_fixup_attached |= prog.section().ends_with("hid_bpf_rdesc_fixup");
+                }
                 Err(e) => {
                     let errstr = match e {
                         libbpf_rs::Error::System(errno) => errno::Errno(-errno).to_string(),
                         _ => e.to_string(),
                     };
                     log::warn!(
-                        "could not pin {} to device id {}, error {}",
-                        &tracing_prog.name(),
+                        "could not attach {} to device id {}, error {}",
+                        prog.name(),
                         hid_id,
                         errstr,
                     );
+
+                    if options.programs_independent {
+                        continue;
+                    }
+
+                    /*
+                     * A half attached object (e.g. the event fix without the
+                     * matching rdesc fixup) is worse than nothing: removing
+                     * the pins detaches what was attached so far.
+                     */
+                    log::warn!(
+                        "detaching the other programs of {} from device id {}",
+                        object_name,
+                        hid_id,
+                    );
+                    for path in &pinned {
+                        fs::remove_file(path).ok();
+                    }
+                    fs::remove_dir(&object_dir).ok();
+                    remove_unused_device_dir(&device.sysname());
+
+                    return Err(e);
                 }
-                Ok(_) => {
-                    attached = true;
-                    log::debug!(target: "libbpf", "Successfully pinned prog at {}", path);
-                }
+            }
+        }
+
+        let mut attached = !pinned.is_empty();
+
+        if !attached {
+            fs::remove_dir(&object_dir).ok();
+            remove_unused_device_dir(&device.sysname());
+        }
+
+        if attached && !moved_functions.is_empty() {
+            let moved: Vec<&AttachedProgram> = others
+                .iter()
+                .filter(|other| {
+                    moved_functions.contains(&other.attach_btf_id) && comes_after(other)
+                })
+                .collect();
+
+            if let Err(e) = self.attach_again(&moved, hid_id) {
+                log::warn!(
+                    "could not attach the programs that come after {} again on device id {}, detaching it",
+                    object_name,
+                    hid_id,
+                );
+                fs::remove_dir_all(&object_dir).ok();
+                remove_unused_device_dir(&device.sysname());
+
+                return Err(e);
+            }
+        }
+
+        if attached && priority != 0 {
+            if let Err(e) = save_priority(&object_dir, priority) {
+                log::warn!(
+                    "could not save the priority of {} on device id {}, error {}",
+                    object_name,
+                    hid_id,
+                    e.to_string(),
+                );
+            }
+        }
+
+        /* only once the report descriptor is actually being fixed */
+        if let Some(rdesc) = original_rdesc.filter(|_| rdesc_fixup_attached) {
+            if let Err(e) = save_original_rdesc(&device.sysname(), &rdesc) {
+                log::warn!(
+                    "could not save the report descriptor of device id {}, error {}",
+                    hid_id,
+                    e.to_string(),
+                );
             }
         }
 
@@ -220,3 +1183,77 @@ impl<'a> HidBPF<'a> {
         Ok(attached)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bpffs_sysname() {
+        let path = get_bpffs_path("0003:04D9:A09F.0009", "");
+        let name = path
+            .trim_start_matches(BPFFS_HID_DIR)
+            .trim_matches('/')
+            .to_string();
+        assert!(name == "0003_04D9_A09F_0009");
+        assert!(sysname_from_bpffs_name(&name) == "0003:04D9:A09F.0009");
+
+        assert!(sysname_from_bpffs_name("foo_bar") == "foo_bar");
+    }
+
+    #[test]
+    fn test_probe_args() {
+        let args = hid_bpf_probe_args::new(3, &[0x05, 0x01, 0x09, 0x02]);
+        assert!(args.hid == 3);
+        assert!(args.rdesc_size == 4);
+        assert!(args.rdesc[..4] == [0x05, 0x01, 0x09, 0x02]);
+        assert!(args.rdesc[4..].iter().all(|b| *b == 0));
+        assert!(args.retval == -1);
+        assert!(args.version == HID_BPF_PROBE_ARGS_VERSION);
+        assert!(args.usb_interface == -1);
+
+        // oversized descriptors are truncated but keep their size
+        let rdesc = vec![0xc0; 5000];
+        let args = hid_bpf_probe_args::new(3, &rdesc);
+        assert!(args.rdesc_size == 5000);
+        assert!(args.rdesc.iter().all(|b| *b == 0xc0));
+    }
+
+    #[test]
+    fn test_copy_c_string() {
+        let as_bytes =
+            |dest: &[std::os::raw::c_char]| -> Vec<u8> { dest.iter().map(|c| *c as u8).collect() };
+        let mut dest: [std::os::raw::c_char; 8] = [0; 8];
+
+        copy_c_string(&mut dest, Some(&String::from("USB")));
+        assert!(as_bytes(&dest) == b"USB\0\0\0\0\0");
+
+        // too long, truncated but still NUL terminated
+        copy_c_string(&mut dest, Some(&String::from("Holtek USB Gaming Mouse")));
+        assert!(as_bytes(&dest) == b"Holtek \0");
+
+        let mut dest: [std::os::raw::c_char; 8] = [0; 8];
+        copy_c_string(&mut dest, None);
+        assert!(as_bytes(&dest) == [0; 8]);
+    }
+
+    #[test]
+    fn test_verifier_log() {
+        let messages = "libbpf: prog 'probe': BPF program load failed: Permission denied\n\
+                        libbpf: prog 'probe': -- BEGIN PROG LOAD LOG --\n\
+                        0: R1=ctx() R10=fp0\n\
+                        0: (61) r2 = *(u32 *)(r1 +4100)\n\
+                        invalid bpf_context access off=4100 size=4\n\
+                        processed 1 insns (limit 1000000) max_states_per_insn 0 total_states 0\n\
+                        -- END PROG LOAD LOG --\n\
+                        libbpf: prog 'probe': failed to load: -13\n";
+
+        let log = verifier_log(messages);
+        assert!(log.starts_with("0: R1=ctx() R10=fp0"));
+        assert!(log.ends_with("total_states 0"));
+        assert!(verifier_processed_insns(&log) == Some(1));
+
+        assert!(verifier_log("  libbpf: no log\n") == "libbpf: no log");
+        assert!(verifier_processed_insns("libbpf: no log").is_none());
+    }
+}
@@ -10,8 +10,12 @@
 #define VID_HOLTEK 0x04D9
 #define PID_G10_MECHANICAL_GAMING_MOUSE 0xA09F
 
+/*
+ * The device exports 3 interfaces.
+ * The mouse interface has a report descriptor of length 71.
+ */
 HID_BPF_CONFIG(
-	HID_DEVICE(BUS_USB, HID_GROUP_GENERIC, VID_HOLTEK, PID_G10_MECHANICAL_GAMING_MOUSE)
+	HID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, VID_HOLTEK, PID_G10_MECHANICAL_GAMING_MOUSE, 71, 71)
 );
 
 SEC("fmod_ret/hid_bpf_device_event")
@@ -36,15 +40,6 @@ int BPF_PROG(hid_y_event, struct hid_bpf_ctx *hctx)
 SEC("syscall")
 int probe(struct hid_bpf_probe_args *ctx)
 {
-	/*
-	 * The device exports 3 interfaces.
-	 * The mouse interface has a report descriptor of length 71.
-	 * So if report descriptor size is not 71, mark as -EINVAL
-	 */
-	ctx->retval = ctx->rdesc_size != 71;
-	if (ctx->retval)
-		ctx->retval = -EINVAL;
-
 	/* comment the following line to actually bind the program */
 	ctx->retval = -EINVAL;
 
@@ -11,7 +11,7 @@
 #define PID_ELITE_PRESENTER 0x464A
 
 HID_BPF_CONFIG(
-	HID_DEVICE(BUS_BLUETOOTH, HID_GROUP_GENERIC, VID_HP, PID_ELITE_PRESENTER)
+	HID_DEVICE_RDESC_SIZE(BUS_BLUETOOTH, HID_GROUP_GENERIC, VID_HP, PID_ELITE_PRESENTER, 264, 264)
 );
 
 
@@ -30,14 +30,4 @@ int BPF_PROG(hid_fix_rdesc, struct hid_bpf_ctx *hctx)
 	return 0;
 }
 
-SEC("syscall")
-int probe(struct hid_bpf_probe_args *ctx)
-{
-	ctx->retval = ctx->rdesc_size != 264;
-	if (ctx->retval)
-		ctx->retval = -EINVAL;
-
-	return 0;
-}
-
 char _license[] SEC("license") = "GPL";
@@ -10,8 +10,9 @@
 #define VID_IOGEAR 0x258A /* VID is shared with SinoWealth and Glorious and prob others */
 #define PID_MOMENTUM 0x0027
 
+/* only bind to the keyboard interface */
 HID_BPF_CONFIG(
-	HID_DEVICE(BUS_USB, HID_GROUP_GENERIC, VID_IOGEAR, PID_MOMENTUM)
+	HID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, VID_IOGEAR, PID_MOMENTUM, 213, 213)
 );
 
 SEC("fmod_ret/hid_bpf_rdesc_fixup")
@@ -39,15 +40,4 @@ int BPF_PROG(hid_fix_rdesc, struct hid_bpf_ctx *hctx)
 	return 0;
 }
 
-SEC("syscall")
-int probe(struct hid_bpf_probe_args *ctx)
-{
-	/* only bind to the keyboard interface */
-	ctx->retval = ctx->rdesc_size != 213;
-	if (ctx->retval)
-		ctx->retval = -EINVAL;
-
-	return 0;
-}
-
 char _license[] SEC("license") = "GPL";
@@ -10,8 +10,11 @@
 #define VID_MICROSOFT 0x045e
 #define PID_XBOX_ELITE_2 0x0b22
 
+#define ORIGINAL_RDESC_SIZE		464
+
 HID_BPF_CONFIG(
-	HID_DEVICE(BUS_BLUETOOTH, HID_GROUP_GENERIC, VID_MICROSOFT, PID_XBOX_ELITE_2)
+	HID_DEVICE_RDESC_SIZE(BUS_BLUETOOTH, HID_GROUP_GENERIC, VID_MICROSOFT, PID_XBOX_ELITE_2,
+			      ORIGINAL_RDESC_SIZE, ORIGINAL_RDESC_SIZE)
 );
 
 /*
@@ -31,9 +34,6 @@ HID_BPF_CONFIG(
  * - we need to change the usage to be buttons from 0x15 to 0x18
  */
 
-#define OFFSET_ASSIGN_SELECTION		211
-#define ORIGINAL_RDESC_SIZE		464
-
 const __u8 rdesc_assign_selection[] = {
 	0x0a, 0x99, 0x00,              //   Usage (Media Select Security)     211
 	0x15, 0x00,                    //   Logical Minimum (0)               214
@@ -90,24 +90,36 @@ const __u8 fixed_rdesc_assign_selection[] = {
 
 _Static_assert(sizeof(rdesc_assign_selection) == sizeof(fixed_rdesc_assign_selection),
 	       "Rdesc and fixed rdesc of different size");
-_Static_assert(sizeof(rdesc_assign_selection) + OFFSET_ASSIGN_SELECTION < ORIGINAL_RDESC_SIZE,
-	       "Rdesc at given offset is too big");
+_Static_assert(sizeof(rdesc_assign_selection) < ORIGINAL_RDESC_SIZE,
+	       "Rdesc is too big");
+
+/*
+ * Where probe() found rdesc_assign_selection in the report descriptor.
+ * udev-hid-bpf attaches hid_fix_rdesc from the object the probe ran in,
+ * so this is set for the device it is attached to.
+ */
+__u32 assign_selection_offset;
 
 SEC("fmod_ret/hid_bpf_rdesc_fixup")
 int BPF_PROG(hid_fix_rdesc, struct hid_bpf_ctx *hctx)
 {
 	__u8 *data = hid_bpf_get_data(hctx, 0 /* offset */, 4096 /* size */);
+	__u32 offset = assign_selection_offset;
 
 	if (!data)
 		return 0; /* EPERM check */
 
+	/* not found by the probe, or out of the report descriptor */
+	if (!offset || offset > 4096 - sizeof(rdesc_assign_selection))
+		return 0;
+
 	/* Check that the device is compatible */
-	if (__builtin_memcmp(data + OFFSET_ASSIGN_SELECTION,
+	if (__builtin_memcmp(data + offset,
 			     rdesc_assign_selection,
 			     sizeof(rdesc_assign_selection)))
 		return 0;
 
-	__builtin_memcpy(data + OFFSET_ASSIGN_SELECTION, fixed_rdesc_assign_selection, sizeof(fixed_rdesc_assign_selection));
+	__builtin_memcpy(data + offset, fixed_rdesc_assign_selection, sizeof(fixed_rdesc_assign_selection));
 
 	return 0;
 }
@@ -115,15 +127,27 @@ int BPF_PROG(hid_fix_rdesc, struct hid_bpf_ctx *hctx)
 SEC("syscall")
 int probe(struct hid_bpf_probe_args *ctx)
 {
-	/* only bind to the keyboard interface */
-	ctx->retval = ctx->rdesc_size != ORIGINAL_RDESC_SIZE;
-	if (ctx->retval)
-		ctx->retval = -EINVAL;
-
-	if (__builtin_memcmp(ctx->rdesc + OFFSET_ASSIGN_SELECTION,
-			     rdesc_assign_selection,
-			     sizeof(rdesc_assign_selection)))
-		ctx->retval = -EINVAL;
+	__u8 rdesc[ORIGINAL_RDESC_SIZE];
+	__u32 i;
+
+	ctx->retval = -EINVAL;
+
+	/*
+	 * the size of the report descriptor is checked by the metadata, and
+	 * the verifier only allows constant offsets in ctx: scan a copy
+	 */
+	__builtin_memcpy(rdesc, ctx->rdesc, sizeof(rdesc));
+
+	for (i = 0; i <= sizeof(rdesc) - sizeof(rdesc_assign_selection); i++) {
+		if (__builtin_memcmp(rdesc + i,
+				     rdesc_assign_selection,
+				     sizeof(rdesc_assign_selection)))
+			continue;
+
+		assign_selection_offset = i;
+		ctx->retval = 0;
+		break;
+	}
 
 	return 0;
 }
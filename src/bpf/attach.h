@@ -11,6 +11,7 @@
 struct attach_prog_args {
 	int prog_fd;
 	unsigned int hid;
+	unsigned int flags;	/* enum hid_bpf_attach_flags */
 	int retval;
 };
 
@@ -5,11 +5,31 @@
 #ifndef ____HID_BPF__H
 #define ____HID_BPF__H
 
+/* The arguments of the probe syscall. New fields are only ever added at the
+ * end, so objects compiled against an older version of this header keep
+ * working: check version before using the fields it introduced.
+ */
+#define HID_BPF_PROBE_ARGS_VERSION 2
+
 struct hid_bpf_probe_args {
 	unsigned int hid;
+	/* the size of the report descriptor, which is truncated in rdesc
+	 * when bigger than sizeof(rdesc)
+	 */
 	unsigned int rdesc_size;
 	unsigned char rdesc[4096];
 	int retval;
+
+	/* version 2 */
+	unsigned int version;
+	unsigned int bus;
+	unsigned int group;
+	unsigned int vid;
+	unsigned int pid;
+	int usb_interface;	/* bInterfaceNumber, -1 if not a USB device */
+	char name[128];		/* HID_NAME */
+	char uniq[64];		/* HID_UNIQ */
+	char phys[64];		/* HID_PHYS */
 };
 
 #endif /* ____HID_BPF__H */
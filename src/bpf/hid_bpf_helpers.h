@@ -86,8 +86,10 @@ extern int hid_bpf_hw_request(struct hid_bpf_ctx *ctx,
  * And we use the __LINE__ to give each of our structs a unique name so the
  * BPF program writer doesn't have to.
  *
+ * $ udev-hid-bpf inspect target/bpf/HP_Elite_Presenter.bpf.o
+ * shows the decoded entries, and
  * $ bpftool btf dump file target/bpf/HP_Elite_Presenter.bpf.o
- * shows the inspection data, start by searching for .hid_bpf_config
+ * shows the raw inspection data, start by searching for .hid_bpf_config
  * and working backwards from that (each entry references the type_id of the
  * content).
  */
@@ -101,6 +103,77 @@ extern int hid_bpf_hw_request(struct hid_bpf_ctx *ctx,
 		__uint(pid, (prod));	\
 	} COMBINE(_entry, __LINE__)
 
+/* Same as HID_DEVICE() but the device must also match the given name, uniq
+ * and phys (the HID_NAME, HID_UNIQ and HID_PHYS udev properties). Each of
+ * those is a glob pattern where '*' and '?' are wildcards, use "" to match
+ * anything:
+ *
+ * HID_DEVICE_MATCH(BUS_USB, HID_GROUP_GENERIC, 0x28BD, 0x093A, "UGTABLET*", "", "")
+ *
+ * Strings can not be stored as array sizes, so the patterns are attached
+ * to the matching fields as BTF decl tags (this requires clang >= 14). The
+ * "name=" prefix ensures the tag is never empty, which the kernel rejects.
+ */
+#define HID_DEVICE_MATCH(b, g, ven, prod, name_pattern, uniq_pattern, phys_pattern) \
+	struct {										\
+		__uint(name, 0) __attribute__((btf_decl_tag("name=" name_pattern)));	\
+		__uint(uniq, 0) __attribute__((btf_decl_tag("uniq=" uniq_pattern)));	\
+		__uint(phys, 0) __attribute__((btf_decl_tag("phys=" phys_pattern)));	\
+		__uint(bus, (b));								\
+		__uint(group, (g));								\
+		__uint(vid, (ven));								\
+		__uint(pid, (prod));								\
+	} COMBINE(_entry, __LINE__)
+
+/* Same as HID_DEVICE() but the report descriptor of the device must also be
+ * between min and max bytes long (both inclusive). This replaces the common
+ * probe() that only checks ctx->rdesc_size:
+ *
+ * HID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, 0x258A, 0x0027, 213, 213)
+ */
+#define HID_DEVICE_RDESC_SIZE(b, g, ven, prod, min, max)	\
+	struct {						\
+		__uint(name, 0);				\
+		__uint(bus, (b));				\
+		__uint(group, (g));				\
+		__uint(vid, (ven));				\
+		__uint(pid, (prod));				\
+		__uint(rdesc_size_min, (min));			\
+		__uint(rdesc_size_max, (max));			\
+	} COMBINE(_entry, __LINE__)
+
+/* Same as HID_DEVICE() but the SHA-256 of the report descriptor of the device
+ * must be the given (hex encoded) string, as printed by
+ * sha256sum /sys/bus/hid/devices/<device>/report_descriptor
+ */
+#define HID_DEVICE_RDESC_SHA256(b, g, ven, prod, sha)	\
+	struct {										\
+		__uint(name, 0);								\
+		__uint(bus, (b));								\
+		__uint(group, (g));								\
+		__uint(vid, (ven));								\
+		__uint(pid, (prod));								\
+		__uint(rdesc_sha256, 0) __attribute__((btf_decl_tag("rdesc_sha256=" sha)));	\
+	} COMBINE(_entry, __LINE__)
+
+/* Same as HID_DEVICE() but the device must also expose a top-level
+ * application collection of the given usage page and usage (use 0 for any
+ * usage of that page). Combined with the *_ANY values this attaches to every
+ * device of a kind, for example every pen:
+ *
+ * HID_DEVICE_APPLICATION(BUS_ANY, HID_GROUP_ANY, HID_VID_ANY, HID_PID_ANY, 0x0D, 0x02)
+ */
+#define HID_DEVICE_APPLICATION(b, g, ven, prod, up, u)	\
+	struct {						\
+		__uint(name, 0);				\
+		__uint(bus, (b));				\
+		__uint(group, (g));				\
+		__uint(vid, (ven));				\
+		__uint(pid, (prod));				\
+		__uint(usage_page, (up));			\
+		__uint(usage, (u));				\
+	} COMBINE(_entry, __LINE__)
+
 /* Macro magic below is to make HID_BPF_CONFIG() look like a function call that
  * we can pass multiple HID_DEVICE() invocations in.
  *
@@ -160,4 +233,33 @@ extern int hid_bpf_hw_request(struct hid_bpf_ctx *ctx,
 	_EXPAND(_ARG, __VA_ARGS__) \
 } _device_ids SEC(".hid_bpf_config")
 
+/* Options applying to all the programs of the object, stored like the
+ * HID_BPF_CONFIG() entries:
+ *
+ * HID_BPF_OPTIONS(
+ *	HID_BPF_PROGRAMS_INDEPENDENT,
+ *	HID_BPF_PRIORITY(10)
+ * );
+ */
+
+/* By default, the programs of an object are attached all together or not at
+ * all. With this option, the ones that could be attached are kept when
+ * another one fails.
+ */
+#define HID_BPF_PROGRAMS_INDEPENDENT	__uint(programs_independent, 1)
+
+/* When several objects are attached to the same device, the programs of the
+ * objects with the highest priority process the events first. The default
+ * priority is 0, it can be negative.
+ *
+ * The size of an array can not be negative, so the priority is stored with
+ * HID_BPF_PRIORITY_BIAS added to it.
+ */
+#define HID_BPF_PRIORITY_BIAS		0x10000
+#define HID_BPF_PRIORITY(p)		__uint(priority, (p) + HID_BPF_PRIORITY_BIAS)
+
+#define HID_BPF_OPTIONS(...)  struct { \
+	_EXPAND(_ARG, __VA_ARGS__) \
+} _options SEC(".hid_bpf_config")
+
 #endif /* __HID_BPF_HELPERS_H */
@@ -12,8 +12,8 @@
 #define PID_ARTIST_24_PRO 0x092D
 
 HID_BPF_CONFIG(
-	HID_DEVICE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_24),
-	HID_DEVICE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_24_PRO)
+	HID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_24, 107, 107),
+	HID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_24_PRO, 107, 107)
 );
 
 /*
@@ -213,11 +213,10 @@ SEC("syscall")
 int probe(struct hid_bpf_probe_args *ctx)
 {
 	/*
-	 * The device exports 3 interfaces.
+	 * The device exports 3 interfaces, the metadata only selects
+	 * the one with a report descriptor of 107 bytes.
 	 */
-	ctx->retval = ctx->rdesc_size != 107;
-	if (ctx->retval)
-		ctx->retval = -EINVAL;
+	ctx->retval = 0;
 
 	/* ensure the kernel isn't fixed already */
 	if (ctx->rdesc[17] != 0x45) /* Eraser */
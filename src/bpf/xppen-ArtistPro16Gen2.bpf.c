@@ -12,8 +12,8 @@
 #define PID_ARTIST_PRO16_GEN2 0x095B
 
 HID_BPF_CONFIG(
-	HID_DEVICE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_PRO14_GEN2),
-	HID_DEVICE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_PRO16_GEN2)
+	HID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_PRO14_GEN2, 113, 113),
+	HID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, VID_UGEE, PID_ARTIST_PRO16_GEN2, 113, 113)
 );
 
 /*
@@ -114,11 +114,10 @@ SEC("syscall")
 int probe(struct hid_bpf_probe_args *ctx)
 {
 	/*
-	 * The device exports 3 interfaces.
+	 * The device exports 3 interfaces, the metadata only selects
+	 * the one with a report descriptor of 113 bytes.
 	 */
-	ctx->retval = ctx->rdesc_size != 113;
-	if (ctx->retval)
-		ctx->retval = -EINVAL;
+	ctx->retval = 0;
 
 	/* ensure the kernel isn't fixed already */
 	if (ctx->rdesc[17] != 0x45) /* Eraser */
@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Compiling standalone .bpf.c files, the way build.rs does for src/bpf/
+
+use crate::bpf;
+use crate::modalias::Modalias;
+use libbpf_cargo::SkeletonBuilder;
+use std::path::{Path, PathBuf};
+
+/// The headers a .bpf.c file can include, bundled so that sources outside of
+/// this repository can be compiled. The libbpf ones are provided by
+/// libbpf-cargo.
+const HEADERS: [(&str, &str); 3] = [
+    ("hid_bpf.h", include_str!("bpf/hid_bpf.h")),
+    ("hid_bpf_helpers.h", include_str!("bpf/hid_bpf_helpers.h")),
+    ("vmlinux.h", include_str!("bpf/vmlinux.h")),
+];
+
+/// The object file for a source: foo.bpf.c gives foo.bpf.o
+pub fn object_path(source: &Path, output_dir: Option<&Path>) -> PathBuf {
+    let mut object = match output_dir {
+        Some(dir) => dir.join(source.file_name().unwrap_or_default()),
+        None => source.to_path_buf(),
+    };
+    object.set_extension("o");
+
+    object
+}
+
+/// Compiles `source` into `object` with the bundled headers and checks that
+/// the result carries HID_BPF_CONFIG metadata. Returns the metadata entries.
+pub fn compile(source: &Path, object: &Path) -> std::io::Result<Vec<Modalias>> {
+    let include_dir =
+        std::env::temp_dir().join(format!("udev-hid-bpf-headers-{}", std::process::id()));
+
+    std::fs::create_dir_all(&include_dir)?;
+    for (name, content) in HEADERS {
+        std::fs::write(include_dir.join(name), content)?;
+    }
+
+    let result = SkeletonBuilder::new()
+        .source(source)
+        .obj(object)
+        .clang_args(format!("-I{}", include_dir.display()))
+        .build();
+
+    std::fs::remove_dir_all(&include_dir).ok();
+
+    result.map_err(|e| {
+        std::io::Error::other(format!("Failed to compile {}: {}", source.display(), e))
+    })?;
+
+    let modaliases = bpf::object_metadata(object).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Failed to read the BTF of {}: {}", object.display(), e),
+        )
+    })?;
+
+    if modaliases.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{} has no HID_BPF_CONFIG metadata, it would never be loaded",
+                source.display()
+            ),
+        ));
+    }
+
+    Ok(modaliases)
+}
+
+/// The hwdb entries tagging the devices an object applies to, in the format
+/// of the 99-hid-bpf.hwdb generated by build.rs
+pub fn hwdb_entries(object: &Path, modaliases: Vec<Modalias>) -> String {
+    let fname = object
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let property: String = fname
+        .trim_end_matches(".bpf.o")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    modaliases
+        .into_iter()
+        .map(|modalias| {
+            format!(
+                "hid-bpf:hid:{}\n HID_BPF_{}={}\n\n",
+                String::from(modalias),
+                property,
+                fname
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_path() {
+        let source = Path::new("/home/user/fix/my-mouse.bpf.c");
+
+        assert!(object_path(source, None) == Path::new("/home/user/fix/my-mouse.bpf.o"));
+        assert!(object_path(source, Some(Path::new("/tmp"))) == Path::new("/tmp/my-mouse.bpf.o"));
+    }
+
+    #[test]
+    fn test_hwdb_entries() {
+        let modalias = Modalias::from_static_str("b0003g0001v000004D9p0000A09F").unwrap();
+        let entries = hwdb_entries(Path::new("target/my-mouse.bpf.o"), vec![modalias]);
+
+        assert!(
+            entries
+                == "hid-bpf:hid:b0003g0001v000004D9p0000A09F\n HID_BPF_MY_MOUSE=my-mouse.bpf.o\n\n"
+        );
+    }
+}
@@ -0,0 +1,293 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! Checks that the system is set up to run HID-BPF programs
+
+use serde::Serialize;
+use std::path::Path;
+
+const BPFFS_MOUNTPOINT: &str = "/sys/fs/bpf";
+const UDEV_RULES: &str = "/etc/udev/rules.d/99-hid-bpf.rules";
+const HWDB: &str = "99-hid-bpf.hwdb";
+/// Where `systemd-hwdb update` writes the compiled hwdb, with and without --usr
+const HWDB_BIN: [&str; 2] = ["/etc/udev/hwdb.bin", "/usr/lib/udev/hwdb.bin"];
+
+const CAP_SYS_ADMIN: u32 = 21;
+const CAP_PERFMON: u32 = 38;
+const CAP_BPF: u32 = 39;
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    pub details: String,
+    /// what to do about it, for failed checks
+    pub remediation: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &str, details: String) -> Self {
+        Check {
+            name: String::from(name),
+            ok: true,
+            details,
+            remediation: None,
+        }
+    }
+
+    fn failed(name: &str, details: String, remediation: &str) -> Self {
+        Check {
+            name: String::from(name),
+            ok: false,
+            details,
+            remediation: Some(String::from(remediation)),
+        }
+    }
+}
+
+/// Whether a bpf filesystem is mounted at `mountpoint`, given the content
+/// of /proc/self/mounts
+fn bpffs_mounted(mounts: &str, mountpoint: &str) -> bool {
+    mounts.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        fields.len() > 2 && fields[1] == mountpoint && fields[2] == "bpf"
+    })
+}
+
+/// The effective capabilities set, given the content of /proc/self/status
+fn effective_capabilities(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+}
+
+fn has_capability(caps: u64, cap: u32) -> bool {
+    caps & (1 << cap) != 0
+}
+
+fn check_bpffs() -> Check {
+    let name = "bpffs";
+    let mounts = std::fs::read_to_string("/proc/self/mounts").unwrap_or_default();
+
+    if bpffs_mounted(&mounts, BPFFS_MOUNTPOINT) {
+        Check::ok(name, format!("bpffs is mounted at {}", BPFFS_MOUNTPOINT))
+    } else {
+        Check::failed(
+            name,
+            format!("no bpffs mounted at {}", BPFFS_MOUNTPOINT),
+            "mount it with `sudo mount -t bpf bpf /sys/fs/bpf`, systemd usually does it at boot",
+        )
+    }
+}
+
+/// Looks up a type in the kernel BTF (/sys/kernel/btf/vmlinux)
+fn kernel_btf_has(name: &str, kind: u32) -> std::io::Result<bool> {
+    let name = std::ffi::CString::new(name)?;
+
+    unsafe {
+        let btf = libbpf_sys::btf__load_vmlinux_btf();
+        if btf.is_null() {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let id = libbpf_sys::btf__find_by_name_kind(btf, name.as_ptr(), kind);
+        libbpf_sys::btf__free(btf);
+
+        Ok(id > 0)
+    }
+}
+
+fn check_kernel() -> Check {
+    let name = "kernel";
+
+    match kernel_btf_has("hid_bpf_attach_prog", libbpf_sys::BTF_KIND_FUNC) {
+        Ok(true) => Check::ok(
+            name,
+            String::from("the hid_bpf_attach_prog kfunc is in the kernel BTF"),
+        ),
+        Ok(false)
+            if kernel_btf_has("hid_bpf_ops", libbpf_sys::BTF_KIND_STRUCT).unwrap_or(false) =>
+        {
+            Check::failed(
+                name,
+                String::from("the kernel uses struct_ops for HID-BPF, not hid_bpf_attach_prog"),
+                "this version of udev-hid-bpf only supports the kernels between 6.3 and 6.10",
+            )
+        }
+        Ok(false) => Check::failed(
+            name,
+            String::from("the hid_bpf_attach_prog kfunc is not in the kernel BTF"),
+            "a kernel 6.3 or later built with CONFIG_HID_BPF=y is needed",
+        ),
+        Err(e) => Check::failed(
+            name,
+            format!("the kernel BTF can not be read: {}", e),
+            "a kernel built with CONFIG_DEBUG_INFO_BTF=y is needed",
+        ),
+    }
+}
+
+fn check_capabilities() -> Check {
+    let name = "capabilities";
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    let caps = match effective_capabilities(&status) {
+        Some(caps) => caps,
+        None => {
+            return Check::failed(
+                name,
+                String::from("the capabilities of the process can not be read"),
+                "check that /proc is mounted",
+            )
+        }
+    };
+
+    if has_capability(caps, CAP_SYS_ADMIN) {
+        Check::ok(name, String::from("CAP_SYS_ADMIN is available"))
+    } else if has_capability(caps, CAP_BPF) && has_capability(caps, CAP_PERFMON) {
+        Check::ok(name, String::from("CAP_BPF and CAP_PERFMON are available"))
+    } else {
+        Check::failed(
+            name,
+            String::from("neither CAP_SYS_ADMIN nor CAP_BPF with CAP_PERFMON are available"),
+            "run udev-hid-bpf as root, udev does it for the installed rules",
+        )
+    }
+}
+
+fn check_udev_rules() -> Check {
+    let name = "udev rules";
+
+    if Path::new(UDEV_RULES).is_file() {
+        Check::ok(name, format!("{} is installed", UDEV_RULES))
+    } else {
+        Check::failed(
+            name,
+            format!("{} is missing", UDEV_RULES),
+            "run `sudo ./install.sh` from the source tree",
+        )
+    }
+}
+
+fn check_hwdb() -> Check {
+    let name = "hwdb";
+    let hwdb = Path::new(crate::HWDB_DIR).join(HWDB);
+
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let hwdb_modified = match modified(&hwdb) {
+        Some(time) => time,
+        None => {
+            return Check::failed(
+                name,
+                format!("{} is missing", hwdb.display()),
+                "run `sudo ./install.sh` from the source tree",
+            )
+        }
+    };
+
+    /* also take the entries of `udev-hid-bpf compile --install` into account */
+    let sources = std::fs::read_dir(crate::HWDB_DIR)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    let fname = path.file_name().unwrap_or_default().to_string_lossy();
+                    fname.starts_with("99-hid-bpf") && fname.ends_with(".hwdb")
+                })
+                .filter_map(|path| modified(&path))
+                .max()
+        })
+        .ok()
+        .flatten()
+        .unwrap_or(hwdb_modified);
+
+    match HWDB_BIN
+        .iter()
+        .filter_map(|bin| modified(Path::new(bin)).map(|time| (bin, time)))
+        .max_by_key(|(_, time)| *time)
+    {
+        Some((bin, time)) if time >= sources => Check::ok(
+            name,
+            format!("{} is installed and compiled in {}", hwdb.display(), bin),
+        ),
+        Some((bin, _)) => Check::failed(
+            name,
+            format!("{} is older than the hid-bpf hwdb files", bin),
+            "run `sudo systemd-hwdb update`",
+        ),
+        None => Check::failed(
+            name,
+            String::from("the hwdb was never compiled"),
+            "run `sudo systemd-hwdb update`",
+        ),
+    }
+}
+
+fn check_bpf_dir(bpf_dir: &Path) -> Check {
+    let name = "bpf objects";
+    let count = std::fs::read_dir(bpf_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter(|entry| entry.path().to_string_lossy().ends_with(".bpf.o"))
+                .count()
+        })
+        .unwrap_or(0);
+
+    if count > 0 {
+        Check::ok(
+            name,
+            format!("{} BPF objects in {}", count, bpf_dir.display()),
+        )
+    } else {
+        Check::failed(
+            name,
+            format!("no BPF objects in {}", bpf_dir.display()),
+            "run `sudo ./install.sh` from the source tree or `sudo udev-hid-bpf compile --install`",
+        )
+    }
+}
+
+/// Runs all the checks, `bpf_dir` being the folder the objects are loaded from
+pub fn checks(bpf_dir: &Path) -> Vec<Check> {
+    vec![
+        check_bpffs(),
+        check_kernel(),
+        check_capabilities(),
+        check_udev_rules(),
+        check_hwdb(),
+        check_bpf_dir(bpf_dir),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bpffs_mounted() {
+        let mounts = "sysfs /sys sysfs rw,nosuid,nodev,noexec,relatime 0 0\n\
+                      bpf /sys/fs/bpf bpf rw,nosuid,nodev,noexec,relatime,mode=700 0 0\n";
+        assert!(bpffs_mounted(mounts, "/sys/fs/bpf"));
+        assert!(!bpffs_mounted(mounts, "/sys"));
+
+        let mounts = "tmpfs /sys/fs/bpf tmpfs rw 0 0\n";
+        assert!(!bpffs_mounted(mounts, "/sys/fs/bpf"));
+    }
+
+    #[test]
+    fn test_effective_capabilities() {
+        let status = "Name:\tudev-hid-bpf\n\
+                      CapInh:\t0000000000000000\n\
+                      CapPrm:\t000001ffffffffff\n\
+                      CapEff:\t000001ffffffffff\n";
+        let caps = effective_capabilities(status).unwrap();
+        assert!(has_capability(caps, CAP_SYS_ADMIN));
+        assert!(has_capability(caps, CAP_BPF));
+
+        let caps = effective_capabilities("CapEff:\t0000000000000000\n").unwrap();
+        assert!(!has_capability(caps, CAP_SYS_ADMIN));
+
+        assert!(effective_capabilities("Name:\tfoo\n").is_none());
+    }
+}
@@ -1,8 +1,10 @@
 // SPDX-License-Identifier: GPL-2.0-only
 
 use crate::bpf;
-use crate::modalias::Modalias;
+use crate::modalias::{Metadata, Modalias};
+use crate::rdesc;
 use log;
+use sha2::{Digest, Sha256};
 
 pub struct HidUdev {
     udev_device: udev::Device,
@@ -39,8 +41,8 @@ impl HidUdev {
         })
     }
 
-    pub fn modalias(&self) -> Modalias {
-        Modalias::from_udev_device(&self.udev_device).unwrap()
+    pub fn modalias(&self) -> std::io::Result<Modalias> {
+        Modalias::from_udev_device(&self.udev_device)
     }
 
     pub fn sysname(&self) -> String {
@@ -56,29 +58,154 @@ impl HidUdev {
         u32::from_str_radix(&hid_sys[15..], 16).unwrap()
     }
 
-    pub fn load_bpf_from_directory(
-        &self,
-        bpf_dir: std::path::PathBuf,
-        prog: Option<String>,
-    ) -> std::io::Result<()> {
-        if !bpf_dir.exists() {
+    /// Returns the `HID_BPF_*` udev properties (as set by our hwdb) of this device
+    pub fn hid_bpf_properties(&self) -> Vec<(String, String)> {
+        self.udev_device
+            .properties()
+            .inspect(|property| {
+                log::debug!("property: {:?} = {:?}", property.name(), property.value())
+            })
+            .filter(|property| property.name().to_string_lossy().starts_with("HID_BPF_"))
+            .map(|property| {
+                (
+                    property.name().to_string_lossy().to_string(),
+                    property.value().to_string_lossy().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    /// The `bInterfaceNumber` of the USB interface of this device, if any
+    pub fn usb_interface(&self) -> Option<u8> {
+        let interface = self
+            .udev_device
+            .parent_with_subsystem_devtype("usb", "usb_interface")
+            .ok()??;
+        let number = interface.attribute_value("bInterfaceNumber")?.to_str()?;
+
+        u8::from_str_radix(number.trim(), 16).ok()
+    }
+
+    /// Unbinds the device from its driver and binds it again, so the kernel
+    /// parses its (possibly fixed) report descriptor again. Nothing to do
+    /// without a driver, the report descriptor is parsed when one binds.
+    pub fn rebind(&self) -> std::io::Result<()> {
+        let driver = self.udev_device.syspath().join("driver");
+        let sysname = self.sysname();
+
+        if !driver.exists() {
+            log::debug!("{} is not bound to a driver, not rebinding it", sysname);
             return Ok(());
         }
+        let driver = std::fs::canonicalize(driver)?;
+
+        std::fs::write(driver.join("unbind"), &sysname)?;
+        std::fs::write(driver.join("bind"), &sysname)
+    }
+
+    pub fn report_descriptor(&self) -> std::io::Result<Vec<u8>> {
+        std::fs::read(self.udev_device.syspath().join("report_descriptor"))
+    }
+
+    /// Whether a metadata entry of a BPF object matches this device, including
+    /// the name, uniq and phys patterns, the report descriptor fingerprint and
+    /// the required application collection.
+    pub fn entry_matches(&self, entry: &Modalias) -> bool {
+        let device = match Modalias::from_udev_device(&self.udev_device) {
+            Ok(modalias) => modalias,
+            Err(_) => return false,
+        };
+
+        if !entry.matches(&device) {
+            return false;
+        }
+
+        /* only read and parse the report descriptor when the entry asks for it */
+        if !entry.has_rdesc_fingerprint() && entry.usage_page.is_none() {
+            return true;
+        }
+
+        let rdesc = match self.report_descriptor() {
+            Ok(rdesc) => rdesc,
+            Err(e) => {
+                log::warn!(
+                    "Failed to read the report descriptor of {}: {}",
+                    self.sysname(),
+                    e
+                );
+                return false;
+            }
+        };
+
+        rdesc_matches(entry, &rdesc)
+    }
 
+    /// The hwdb only knows about the modalias, so check the full metadata of an
+    /// object (name, uniq and phys patterns, report descriptor fingerprint)
+    /// against this device. Objects without metadata are accepted.
+    pub fn metadata_matches(&self, object: &std::path::Path) -> bool {
+        let btf = match libbpf_rs::btf::Btf::from_path(object) {
+            Ok(btf) => btf,
+            Err(_) => return true,
+        };
+        let entries: Vec<Modalias> = match Metadata::from_btf(&btf) {
+            Some(metadata) => metadata.modaliases().collect(),
+            None => return true,
+        };
+
+        if entries.is_empty() || entries.iter().any(|entry| self.entry_matches(entry)) {
+            return true;
+        }
+
+        log::debug!(
+            "device {} doesn't match the metadata of {}, ignoring it",
+            self.sysname(),
+            object.display(),
+        );
+
+        false
+    }
+
+    /// Returns the list of BPF objects in `bpf_dir` that should be loaded for this
+    /// device, either the explicitly given `prog` or the ones tagged through
+    /// the `HID_BPF_*` udev properties. Without such properties (e.g. no hwdb
+    /// installed), the metadata of the objects is matched against the device.
+    pub fn find_bpf_objects(
+        &self,
+        bpf_dir: &std::path::Path,
+        prog: Option<String>,
+    ) -> Vec<std::path::PathBuf> {
         let mut paths = Vec::new();
 
+        if !bpf_dir.exists() {
+            return paths;
+        }
+
         if prog.is_none() {
-            for property in self.udev_device.properties() {
-                log::debug!("property: {:?} = {:?}", property.name(), property.value());
-                if property.name().to_str().unwrap().starts_with("HID_BPF_") {
-                    let target_object = bpf_dir.join(property.value());
-                    if target_object.is_file() {
+            let properties = self.hid_bpf_properties();
+
+            for (_, value) in &properties {
+                let target_object = bpf_dir.join(value);
+                if target_object.is_file() && self.metadata_matches(&target_object) {
+                    log::debug!(
+                        "device added {}, filename: {}",
+                        self.sysname(),
+                        target_object.display(),
+                    );
+                    paths.push(target_object);
+                }
+            }
+
+            /* no hwdb entry for this device, match the metadata ourselves */
+            if properties.is_empty() {
+                for (path, metadata) in bpf::objects_metadata(bpf_dir).unwrap_or_default() {
+                    if metadata.iter().any(|entry| self.entry_matches(entry)) {
                         log::debug!(
-                            "device added {}, filename: {}",
+                            "device added {}, matching metadata in: {}",
                             self.sysname(),
-                            target_object.display(),
+                            path.display(),
                         );
-                        paths.push(target_object);
+                        paths.push(path);
                     }
                 }
             }
@@ -94,18 +221,72 @@ impl HidUdev {
             }
         }
 
+        paths
+    }
+
+    pub fn load_bpf_from_directory(
+        &self,
+        bpf_dir: std::path::PathBuf,
+        prog: Option<String>,
+        priority: Option<i32>,
+    ) -> std::io::Result<()> {
+        let paths = self.find_bpf_objects(&bpf_dir, prog);
+
         if !paths.is_empty() {
             let hid_bpf_loader = bpf::HidBPF::new().unwrap();
-            for path in paths {
-                if let Err(e) = hid_bpf_loader.load_programs(&path, self) {
-                    log::warn!("Failed to load {:?}: {:?}", path, e);
-                };
-            }
+            self.load_bpf_objects(&hid_bpf_loader, paths, |_| priority);
         }
 
         Ok(())
     }
 
+    /// Same as [`HidUdev::load_bpf_from_directory`] but reuses an already loaded
+    /// `HidBPF`, so long-running callers do not reload the attach skeleton for
+    /// every device. `priorities` overrides the priority of the objects by
+    /// file name. Failures are logged per object.
+    pub fn load_bpf_from_directory_with(
+        &self,
+        hid_bpf_loader: &bpf::HidBPF,
+        bpf_dir: &std::path::Path,
+        prog: Option<String>,
+        priorities: &std::collections::HashMap<String, i32>,
+    ) {
+        let paths = self.find_bpf_objects(bpf_dir, prog);
+
+        self.load_bpf_objects(hid_bpf_loader, paths, |path| {
+            let file_name = path.file_name()?.to_string_lossy();
+            priorities.get(file_name.as_ref()).copied()
+        });
+    }
+
+    /// Loads the objects by decreasing priority (then by name), so that the
+    /// kernel runs their programs in that order. `priority` gives the
+    /// priority to use instead of the one in the metadata of an object.
+    fn load_bpf_objects(
+        &self,
+        hid_bpf_loader: &bpf::HidBPF,
+        paths: Vec<std::path::PathBuf>,
+        priority: impl Fn(&std::path::Path) -> Option<i32>,
+    ) {
+        let mut objects: Vec<(i32, std::path::PathBuf)> = paths
+            .into_iter()
+            .map(|path| {
+                (
+                    priority(&path).unwrap_or_else(|| bpf::object_priority(&path)),
+                    path,
+                )
+            })
+            .collect();
+
+        objects.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        for (priority, path) in objects {
+            if let Err(e) = hid_bpf_loader.load_programs(&path, self, Some(priority)) {
+                log::warn!("Failed to load {:?}: {:?}", path, e);
+            };
+        }
+    }
+
     pub fn remove_bpf_objects(&self) -> std::io::Result<()> {
         log::info!("device removed");
 
@@ -117,6 +298,31 @@ impl HidUdev {
     }
 }
 
+/// Whether a report descriptor has the size, SHA-256 and application
+/// collection a metadata entry asks for
+pub fn rdesc_matches(entry: &Modalias, rdesc: &[u8]) -> bool {
+    if entry.has_rdesc_fingerprint() {
+        let sha256 = match entry.rdesc_sha256 {
+            Some(_) => format!("{:x}", Sha256::digest(rdesc)),
+            None => String::new(),
+        };
+        if !entry.matches_rdesc(rdesc.len(), &sha256) {
+            return false;
+        }
+    }
+
+    if entry.usage_page.is_none() {
+        return true;
+    }
+
+    let applications = rdesc::application_usages(rdesc).unwrap_or_else(|e| {
+        log::warn!("Failed to parse the report descriptor: {}", e);
+        Vec::new()
+    });
+
+    entry.matches_applications(&applications)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
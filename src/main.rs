@@ -1,13 +1,17 @@
 // SPDX-License-Identifier: GPL-2.0-only
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use libbpf_rs;
 use log;
 use regex::Regex;
+use serde::Serialize;
 
 pub mod bpf;
+pub mod compile;
+pub mod doctor;
 pub mod hidudev;
 pub mod modalias;
+pub mod rdesc;
 
 static DEFAULT_BPF_DIR: &str = "/usr/local/lib/firmware/hid/bpf";
 
@@ -23,10 +27,21 @@ struct Cli {
     /// Enable verbose output
     #[arg(short, long, default_value_t = false)]
     verbose: bool,
+    /// Output format of the informational commands
+    #[arg(long, global = true, value_enum, default_value_t = Format::Human)]
+    format: Format,
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Format {
+    /// Human readable output
+    Human,
+    /// JSON output, for scripts
+    Json,
+}
+
 fn print_to_log(level: libbpf_rs::PrintLevel, msg: String) {
     match level {
         libbpf_rs::PrintLevel::Debug => log::debug!(target: "libbpf", "{}", msg.trim()),
@@ -46,6 +61,11 @@ enum Commands {
         /// Folder to look at for bpf objects
         #[arg(short, long)]
         bpfdir: Option<std::path::PathBuf>,
+        /// Priority of the objects, instead of the one in their metadata. Objects
+        /// with a higher priority process the events first: the programs of the
+        /// objects already attached that come after them are attached again
+        #[arg(long, allow_negative_numbers = true)]
+        priority: Option<i32>,
     },
     /// A device is removed from the sysfs
     Remove {
@@ -59,7 +79,116 @@ enum Commands {
         bpfdir: Option<std::path::PathBuf>,
     },
     /// List available devices
-    ListDevices {},
+    ListDevices {
+        /// Folder to look at for bpf objects
+        #[arg(short, long)]
+        bpfdir: Option<std::path::PathBuf>,
+    },
+    /// Show the metadata, programs and maps embedded in a BPF object
+    Inspect {
+        /// The BPF object to inspect, e.g. target/bpf/HP_Elite_Presenter.bpf.o
+        object: std::path::PathBuf,
+    },
+    /// Load BPF objects without attaching them and show the verifier log of each program
+    Verify {
+        /// The BPF objects to verify, e.g. target/bpf/HP_Elite_Presenter.bpf.o
+        #[arg(required = true)]
+        objects: Vec<std::path::PathBuf>,
+    },
+    /// Explain which BPF objects would be loaded for a device, without attaching them
+    Match {
+        /// sysfs path to a device, e.g. /sys/bus/hid/devices/0003:045E:07A5.000B
+        devpath: std::path::PathBuf,
+        /// Only consider this BPF program
+        prog: Option<String>,
+        /// Folder to look at for bpf objects
+        #[arg(short, long)]
+        bpfdir: Option<std::path::PathBuf>,
+    },
+    /// Check the metadata and run the probe of a BPF object against a report descriptor
+    /// saved in a file
+    Probe {
+        /// The BPF object whose probe should be run
+        #[arg(short, long)]
+        object: std::path::PathBuf,
+        /// The report descriptor, either raw bytes or a hid-recorder output
+        #[arg(short, long)]
+        rdesc: std::path::PathBuf,
+        /// The HID id given to the probe
+        #[arg(long, default_value_t = 0)]
+        hid_id: u32,
+    },
+    /// Print the report descriptor of a device, decoded one item per line
+    Rdesc {
+        /// sysfs path or name of a device, e.g. 0003:045E:07A5.000B, or a file with
+        /// a report descriptor (raw bytes or a hid-recorder output)
+        device: std::path::PathBuf,
+        /// Print a C array ready to be pasted in a .bpf.c file
+        #[arg(long, default_value_t = false)]
+        c_array: bool,
+    },
+    /// Show what the attached rdesc_fixup programs changed in the report descriptor of a device
+    RdescDiff {
+        /// sysfs path or name of a device, e.g. 0003:045E:07A5.000B
+        device: std::path::PathBuf,
+        /// The original report descriptor (raw bytes or a hid-recorder output), instead
+        /// of the copy saved when the programs were attached
+        #[arg(long)]
+        original: Option<std::path::PathBuf>,
+    },
+    /// Create a new .bpf.c file for a device, ready to be filled in
+    New {
+        /// sysfs path or name of a device, e.g. 0003:045E:07A5.000B
+        device: std::path::PathBuf,
+        /// The name of the fix, used for the file name and the program names
+        identifier: String,
+        /// Folder to write the .bpf.c file to
+        #[arg(short, long, default_value = "src/bpf")]
+        output_dir: std::path::PathBuf,
+    },
+    /// Compile .bpf.c files into BPF objects, without rebuilding udev-hid-bpf
+    Compile {
+        /// The .bpf.c files to compile
+        #[arg(required = true)]
+        sources: Vec<std::path::PathBuf>,
+        /// Folder to write the .bpf.o files to, next to the sources by default
+        #[arg(short, long)]
+        output_dir: Option<std::path::PathBuf>,
+        /// Install the objects in the bpf folder and their hwdb entries in /etc/udev/hwdb.d
+        #[arg(long, default_value_t = false)]
+        install: bool,
+        /// Folder to install the bpf objects to
+        #[arg(short, long)]
+        bpfdir: Option<std::path::PathBuf>,
+    },
+    /// Attach a .bpf.c file to a device, and compile and attach it again whenever it changes
+    Dev {
+        /// sysfs path or name of a device, e.g. 0003:045E:07A5.000B
+        device: std::path::PathBuf,
+        /// The .bpf.c file being worked on
+        source: std::path::PathBuf,
+        /// Remove the BPF programs already attached to the device instead of failing
+        #[arg(long, default_value_t = false)]
+        replace: bool,
+    },
+    /// Show the BPF programs and maps currently attached to each device
+    Status {},
+    /// Check whether this system can run HID-BPF programs
+    Doctor {
+        /// Folder to look at for bpf objects
+        #[arg(short, long)]
+        bpfdir: Option<std::path::PathBuf>,
+    },
+    /// Watch udev for HID devices and load/unload BPF programs as they come and go
+    Daemon {
+        /// Folder to look at for bpf objects
+        #[arg(short, long)]
+        bpfdir: Option<std::path::PathBuf>,
+        /// Priority of an object instead of the one in its metadata, e.g.
+        /// trace_hid_events.bpf.o=-10. Can be given several times
+        #[arg(long, value_parser = parse_object_priority)]
+        priority: Vec<(String, i32)>,
+    },
 }
 
 fn default_bpf_dir() -> std::path::PathBuf {
@@ -75,6 +204,7 @@ fn cmd_add(
     syspath: &std::path::PathBuf,
     prog: Option<String>,
     bpfdir: Option<std::path::PathBuf>,
+    priority: Option<i32>,
 ) -> std::io::Result<()> {
     let dev = hidudev::HidUdev::from_syspath(syspath)?;
     let target_bpf_dir = match bpfdir {
@@ -82,7 +212,7 @@ fn cmd_add(
         None => default_bpf_dir(),
     };
 
-    dev.load_bpf_from_directory(target_bpf_dir, prog)
+    dev.load_bpf_from_directory(target_bpf_dir, prog, priority)
 }
 
 fn sysname_from_syspath(syspath: &std::path::PathBuf) -> std::io::Result<String> {
@@ -108,90 +238,1303 @@ fn cmd_remove(syspath: &std::path::PathBuf) -> std::io::Result<()> {
     bpf::remove_bpf_objects(&sysname)
 }
 
-fn cmd_list_bpf_programs(bpfdir: Option<std::path::PathBuf>) -> std::io::Result<()> {
-    let dir = bpfdir.or(Some(default_bpf_dir())).unwrap();
-    println!(
-        "Showing available BPF files in {}:",
-        dir.as_path().to_str().unwrap()
+fn cmd_inspect(object: &std::path::PathBuf, format: Format) -> std::io::Result<()> {
+    let info = bpf::inspect_object(object).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Failed to open {}: {}", object.display(), e),
+        )
+    })?;
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+        return Ok(());
+    }
+
+    println!("{}", info.path);
+    println!("  - modaliases:");
+    for modalias in info.modaliases {
+        println!(
+            "    - {} (bus 0x{:04X}, group 0x{:04X}, vid 0x{:04X}, pid 0x{:04X})",
+            modalias.modalias, modalias.bus, modalias.group, modalias.vid, modalias.pid
+        );
+        for (property, pattern) in [
+            ("name", modalias.name),
+            ("uniq", modalias.uniq),
+            ("phys", modalias.phys),
+        ] {
+            if let Some(pattern) = pattern {
+                println!("      {property}: \"{pattern}\"");
+            }
+        }
+        match (modalias.rdesc_size_min, modalias.rdesc_size_max) {
+            (None, None) => {}
+            (min, max) => println!(
+                "      rdesc size: {}..{}",
+                min.map(|m| m.to_string()).unwrap_or_default(),
+                max.map(|m| m.to_string()).unwrap_or_default(),
+            ),
+        }
+        if let Some(sha256) = modalias.rdesc_sha256 {
+            println!("      rdesc sha256: {sha256}");
+        }
+        if let Some(usage_page) = modalias.usage_page {
+            match modalias.usage {
+                Some(usage) => println!("      application: {usage_page:#06X}/{usage:#06X}"),
+                None => println!("      application: {usage_page:#06X}/any"),
+            }
+        }
+    }
+    println!("  - priority: {}", info.priority);
+    if info.programs_independent {
+        println!("  - programs (independent):");
+    } else {
+        println!("  - programs:");
+    }
+    for prog in info.programs {
+        println!("    - {} ({})", prog.name, prog.section);
+    }
+    println!("  - maps:");
+    for map in info.maps {
+        println!("    - {map}");
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct ObjectMatch {
+    object: String,
+    /// why the loader would pick this object: a HID_BPF_* udev property, the
+    /// command line or its metadata. None if it would not be picked.
+    selected_by: Option<String>,
+    /// the metadata entries of the object that match the device
+    modaliases: Vec<String>,
+    /// the retval of the probe, or None if there is no probe program
+    probe: Option<i32>,
+    probe_error: Option<String>,
+    would_attach: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceMatch {
+    syspath: String,
+    modalias: String,
+    udev_properties: Vec<(String, String)>,
+    objects: Vec<ObjectMatch>,
+}
+
+fn cmd_match(
+    syspath: &std::path::PathBuf,
+    prog: Option<String>,
+    bpfdir: Option<std::path::PathBuf>,
+    format: Format,
+) -> std::io::Result<()> {
+    let dev = hidudev::HidUdev::from_syspath(syspath)?;
+    let target_bpf_dir = match bpfdir {
+        Some(bpf_dir) => bpf_dir,
+        None => default_bpf_dir(),
+    };
+    let device_modalias = dev.modalias()?;
+    let udev_properties = dev.hid_bpf_properties();
+    let selected = dev.find_bpf_objects(&target_bpf_dir, prog.clone());
+
+    let mut candidates = bpf::objects_metadata(&target_bpf_dir)?;
+    if prog.is_some() {
+        candidates.retain(|(path, _)| selected.contains(path));
+    }
+
+    let mut objects = Vec::new();
+    for (path, metadata) in candidates {
+        let fname = path.file_name().unwrap().to_string_lossy().to_string();
+
+        let modaliases: Vec<String> = metadata
+            .into_iter()
+            .filter(|modalias| dev.entry_matches(modalias))
+            .map(String::from)
+            .collect();
+
+        let is_selected = selected.contains(&path);
+        let selected_by = if !is_selected {
+            None
+        } else if prog.is_some() {
+            Some(String::from("command line"))
+        } else {
+            udev_properties
+                .iter()
+                .find(|(_, value)| *value == fname)
+                .map(|(name, _)| name.clone())
+                .or(Some(String::from("metadata")))
+        };
+
+        /* only show the objects that have something to do with this device */
+        if !is_selected && modaliases.is_empty() {
+            continue;
+        }
+
+        let (probe, probe_error) = if is_selected {
+            match bpf::probe_object(&path, &dev) {
+                Ok(retval) => (retval, None),
+                Err(e) => (None, Some(e.to_string())),
+            }
+        } else {
+            (None, None)
+        };
+
+        objects.push(ObjectMatch {
+            object: fname,
+            selected_by,
+            modaliases,
+            probe,
+            probe_error: probe_error.clone(),
+            would_attach: is_selected && probe_error.is_none() && probe.unwrap_or(0) == 0,
+        });
+    }
+
+    let report = DeviceMatch {
+        syspath: dev.syspath(),
+        modalias: String::from(device_modalias),
+        udev_properties,
+        objects,
+    };
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!("{}", report.syspath);
+    println!("  - modalias: {}", report.modalias);
+    println!("  - udev properties:");
+    for (name, value) in &report.udev_properties {
+        println!("    - {name}={value}");
+    }
+    for object in report.objects {
+        println!("  - {}:", object.object);
+        let selected = object.selected_by.is_some();
+        match object.selected_by {
+            Some(reason) => println!("    - selected by: {reason}"),
+            None => println!("    - selected by: nothing (hwdb not up to date?)"),
+        }
+        if object.modaliases.is_empty() {
+            println!("    - matching metadata: none");
+        }
+        for modalias in object.modaliases {
+            println!("    - matching metadata: {modalias}");
+        }
+        match (object.probe, object.probe_error) {
+            (_, Some(e)) => println!("    - probe: failed to run: {e}"),
+            (Some(retval), None) => println!("    - probe: returned {retval}"),
+            (None, None) if selected => println!("    - probe: no probe program"),
+            (None, None) => println!("    - probe: not run"),
+        }
+        if object.would_attach {
+            println!("    - result: would be attached");
+        } else {
+            println!("    - result: would not be attached");
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts a report descriptor from the content of a file, either as raw
+/// bytes (e.g. a copy of the sysfs report_descriptor) or from the "R:" line
+/// of a hid-recorder output
+fn parse_rdesc_data(data: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    let einval = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+
+    let line = match std::str::from_utf8(&data)
+        .ok()
+        .and_then(|text| text.lines().find(|line| line.starts_with("R: ")))
+    {
+        Some(line) => line,
+        None => return Ok(data),
+    };
+
+    let mut bytes = line[3..].split_whitespace();
+    let length: usize = bytes
+        .next()
+        .and_then(|length| length.parse().ok())
+        .ok_or(einval("Invalid report descriptor length"))?;
+    let rdesc = bytes
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| einval("Invalid byte in report descriptor"))?;
+
+    if rdesc.len() != length {
+        return Err(einval("Report descriptor length mismatch"));
+    }
+
+    Ok(rdesc)
+}
+
+fn read_rdesc_file(path: &std::path::Path) -> std::io::Result<Vec<u8>> {
+    parse_rdesc_data(std::fs::read(path)?)
+}
+
+fn cmd_probe(
+    object: &std::path::PathBuf,
+    rdesc: &std::path::PathBuf,
+    hid_id: u32,
+) -> std::io::Result<()> {
+    let rdesc = read_rdesc_file(rdesc)?;
+
+    /* the loader checks the report descriptor part of the metadata first */
+    let entries: Vec<modalias::Modalias> = bpf::object_metadata(object)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| entry.has_rdesc_fingerprint() || entry.usage_page.is_some())
+        .collect();
+    if !entries.is_empty() {
+        if !entries
+            .iter()
+            .any(|entry| hidudev::rdesc_matches(entry, &rdesc))
+        {
+            println!("metadata: no match");
+            return Ok(());
+        }
+        println!("metadata: match");
+    }
+
+    match bpf::probe_object_with_rdesc(object, hid_id, &rdesc) {
+        Ok(Some((retval, variables))) => {
+            println!("retval: {retval}");
+            for variable in variables {
+                println!(
+                    "{} {} = {:02x?}",
+                    variable.section, variable.name, variable.value
+                );
+            }
+            Ok(())
+        }
+        Ok(None) if !entries.is_empty() => Ok(()),
+        Ok(None) => Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{} has no probe program", object.display()),
+        )),
+        Err(e) => Err(std::io::Error::other(format!(
+            "Failed to run the probe of {}: {}",
+            object.display(),
+            e
+        ))),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct VerifiedObject {
+    path: String,
+    programs: Vec<bpf::VerifiedProgram>,
+}
+
+fn cmd_verify(objects: &[std::path::PathBuf], format: Format) -> std::io::Result<()> {
+    let mut verified = Vec::new();
+
+    for object in objects {
+        let programs = bpf::verify_object(object).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Failed to open {}: {}", object.display(), e),
+            )
+        })?;
+
+        verified.push(VerifiedObject {
+            path: object.display().to_string(),
+            programs,
+        });
+    }
+
+    let rejected = verified
+        .iter()
+        .flat_map(|object| object.programs.iter())
+        .filter(|prog| !prog.accepted)
+        .count();
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&verified)?);
+    } else {
+        for object in &verified {
+            println!("{}", object.path);
+            for prog in &object.programs {
+                let processed = prog
+                    .processed
+                    .map_or(String::from("?"), |count| count.to_string());
+                println!(
+                    "  - {} ({}): {}, {} instructions, {} processed",
+                    prog.name,
+                    prog.section,
+                    if prog.accepted {
+                        "accepted"
+                    } else {
+                        "rejected"
+                    },
+                    prog.instructions,
+                    processed,
+                );
+                if let Some(error) = &prog.error {
+                    println!("    error: {}", error);
+                }
+                for line in prog.log.lines() {
+                    println!("    | {}", line);
+                }
+            }
+            println!();
+        }
+    }
+
+    if rejected > 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} program(s) rejected by the verifier", rejected),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Finds a device from its sysfs path or its name
+fn hid_device(device: &std::path::Path) -> std::io::Result<hidudev::HidUdev> {
+    let syspath = if device.exists() {
+        device.to_path_buf()
+    } else {
+        std::path::PathBuf::from("/sys/bus/hid/devices").join(device)
+    };
+
+    hidudev::HidUdev::from_syspath(&syspath)
+}
+
+/// Reads the report descriptor of a device (sysfs path or name) or from a file
+fn read_device_rdesc(device: &std::path::Path) -> std::io::Result<Vec<u8>> {
+    if device.is_file() {
+        return read_rdesc_file(device);
+    }
+
+    let dev = hid_device(device)?;
+    if let Some(note) = fixed_rdesc_note(&dev) {
+        log::warn!("{}", note);
+    }
+
+    dev.report_descriptor()
+}
+
+/// Whether the report descriptor the kernel exposes for the device may not be
+/// the original one, because BPF programs are attached to it
+fn fixed_rdesc_note(dev: &hidudev::HidUdev) -> Option<String> {
+    let sysname = dev.sysname();
+
+    if !bpf::has_bpf_objects(&sysname) {
+        return None;
+    }
+
+    match bpf::original_rdesc(&sysname) {
+        Ok(Some(_)) => Some(format!(
+            "{} has a fixed report descriptor, rdesc-diff shows the changes",
+            sysname
+        )),
+        _ => Some(format!(
+            "{} has BPF programs attached and no saved copy of its original report \
+             descriptor, this one may be fixed",
+            sysname
+        )),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RdescItem {
+    offset: usize,
+    bytes: Vec<u8>,
+    depth: usize,
+    description: String,
+}
+
+fn cmd_rdesc(device: &std::path::Path, c_array: bool, format: Format) -> std::io::Result<()> {
+    let rdesc = read_device_rdesc(device)?;
+
+    if c_array {
+        print!("{}", rdesc::c_array("fixed_rdesc", &rdesc)?);
+        return Ok(());
+    }
+
+    let items = rdesc::annotate(&rdesc)?;
+
+    if format == Format::Json {
+        let items: Vec<RdescItem> = items
+            .into_iter()
+            .map(|item| RdescItem {
+                offset: item.item.offset,
+                bytes: item.item.raw,
+                depth: item.depth,
+                description: item.description,
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&items)?);
+        return Ok(());
+    }
+
+    println!("# report descriptor length: {} bytes", rdesc.len());
+    for item in items {
+        println!("{}", item.line());
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct RdescDiffLine {
+    /// "same", "removed" or "added"
+    change: String,
+    offset: usize,
+    bytes: Vec<u8>,
+    description: String,
+}
+
+fn cmd_rdesc_diff(
+    device: &std::path::Path,
+    original: Option<std::path::PathBuf>,
+    format: Format,
+) -> std::io::Result<()> {
+    let dev = hid_device(device)?;
+    let fixed = dev.report_descriptor()?;
+    let source = match &original {
+        Some(path) => path.display().to_string(),
+        None => String::from("original, saved when attaching"),
+    };
+    let original = match original {
+        Some(path) => read_rdesc_file(&path)?,
+        None => bpf::original_rdesc(&dev.sysname())
+            .map_err(|e| {
+                std::io::Error::other(format!("Failed to read the saved report descriptor: {}", e))
+            })?
+            .ok_or(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!(
+                    "No original report descriptor saved for {}, no rdesc_fixup program \
+                     was attached by this version of udev-hid-bpf. The kernel only exposes \
+                     the fixed one, use --original with a recording",
+                    dev.sysname()
+                ),
+            ))?,
+    };
+
+    let lines = rdesc::diff(&original, &fixed)?;
+
+    if format == Format::Json {
+        let lines: Vec<RdescDiffLine> = lines
+            .into_iter()
+            .map(|line| {
+                let change = match line {
+                    rdesc::DiffLine::Same(_) => "same",
+                    rdesc::DiffLine::Removed(_) => "removed",
+                    rdesc::DiffLine::Added(_) => "added",
+                };
+                let item = line.item();
+                RdescDiffLine {
+                    change: String::from(change),
+                    offset: item.item.offset,
+                    bytes: item.item.raw.clone(),
+                    description: item.description.clone(),
+                }
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&lines)?);
+        return Ok(());
+    }
+
+    println!("--- {} ({} bytes)", source, original.len());
+    println!("+++ {} ({} bytes)", dev.sysname(), fixed.len());
+
+    /* only print the changes with 3 items of context, like diff -u */
+    let changed: Vec<bool> = lines
+        .iter()
+        .map(|l| !matches!(l, rdesc::DiffLine::Same(_)))
+        .collect();
+    let mut previous = None;
+    for (idx, line) in lines.iter().enumerate() {
+        let start = idx.saturating_sub(3);
+        let end = (idx + 4).min(lines.len());
+        if !changed[start..end].iter().any(|c| *c) {
+            continue;
+        }
+        if idx == 0 || previous != Some(idx - 1) {
+            println!("@@ offset {} @@", line.item().item.offset);
+        }
+        println!("{}", line.line());
+        previous = Some(idx);
+    }
+
+    Ok(())
+}
+
+/// The content of a new .bpf.c file for a device: its HID_BPF_CONFIG, matching
+/// its current report descriptor size, and stub programs to fill in
+fn bpf_source_template(
+    identifier: &str,
+    modalias: &modalias::Modalias,
+    rdesc: &[u8],
+) -> std::io::Result<String> {
+    let prefix: String = identifier
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let parsed = rdesc::ReportDescriptor::parse(rdesc)?;
+    /* the largest input report, including the report ID */
+    let event_size = parsed
+        .reports
+        .iter()
+        .filter(|r| r.kind == rdesc::ReportKind::Input)
+        .map(|r| r.bits().div_ceil(8))
+        .max()
+        .unwrap_or(64);
+    let annotated: String = rdesc::annotate(rdesc)?
+        .iter()
+        .map(|item| format!(" * {}\n", item.line()))
+        .collect();
+    /* the name goes in a C comment, it must not be able to close it */
+    let name: String = modalias
+        .name
+        .as_deref()
+        .unwrap_or("unknown device")
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .replace("*/", "* /");
+
+    Ok(format!(
+        r#"// SPDX-License-Identifier: GPL-2.0-only
+
+#include "vmlinux.h"
+#include "hid_bpf.h"
+#include "hid_bpf_helpers.h"
+#include <bpf/bpf_tracing.h>
+
+/* {name} */
+HID_BPF_CONFIG(
+	{hid_device}
+);
+
+/*
+ * The report descriptor of the device ({rdesc_size} bytes):
+ *
+{annotated} */
+
+SEC("fmod_ret/hid_bpf_rdesc_fixup")
+int BPF_PROG({prefix}_fix_rdesc, struct hid_bpf_ctx *hctx)
+{{
+	__u8 *data = hid_bpf_get_data(hctx, 0 /* offset */, 4096 /* size */);
+
+	if (!data)
+		return 0; /* EPERM check */
+
+	/* change the report descriptor in data here */
+
+	return 0;
+}}
+
+SEC("fmod_ret/hid_bpf_device_event")
+int BPF_PROG({prefix}_fix_event, struct hid_bpf_ctx *hctx)
+{{
+	__u8 *data = hid_bpf_get_data(hctx, 0 /* offset */, {event_size} /* size */);
+
+	if (!data)
+		return 0; /* EPERM check */
+
+	/* change the event in data here */
+
+	return 0;
+}}
+
+SEC("syscall")
+int probe(struct hid_bpf_probe_args *ctx)
+{{
+	ctx->retval = 0;
+
+	/* only bind to the interface with this report descriptor */
+	if (ctx->rdesc_size != {rdesc_size})
+		ctx->retval = -EINVAL;
+
+	return 0;
+}}
+
+char _license[] SEC("license") = "GPL";
+"#,
+        hid_device = modalias.hid_device_rdesc_size_entry(rdesc.len()),
+        rdesc_size = rdesc.len(),
+    ))
+}
+
+fn valid_identifier(identifier: &str) -> bool {
+    !identifier.is_empty()
+        && identifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn cmd_new(
+    device: &std::path::Path,
+    identifier: &str,
+    output_dir: &std::path::Path,
+) -> std::io::Result<()> {
+    /* the identifier ends up in a path, keep it within output_dir */
+    if !valid_identifier(identifier) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid identifier '{}', only letters, digits, '_' and '-' are allowed",
+                identifier
+            ),
+        ));
+    }
+
+    let dev = hid_device(device)?;
+    let rdesc = dev.report_descriptor()?;
+    let path = output_dir.join(format!("{}.bpf.c", identifier));
+
+    if path.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", path.display()),
+        ));
+    }
+
+    std::fs::write(
+        &path,
+        bpf_source_template(identifier, &dev.modalias()?, &rdesc)?,
+    )?;
+    println!("{}", path.display());
+
+    Ok(())
+}
+
+static HWDB_DIR: &str = "/etc/udev/hwdb.d";
+
+fn cmd_compile(
+    sources: &[std::path::PathBuf],
+    output_dir: Option<std::path::PathBuf>,
+    install: bool,
+    bpfdir: Option<std::path::PathBuf>,
+) -> std::io::Result<()> {
+    let target_bpf_dir = match bpfdir {
+        Some(bpf_dir) => bpf_dir,
+        None => default_bpf_dir(),
+    };
+
+    for source in sources {
+        let object = compile::object_path(source, output_dir.as_deref());
+        let modaliases = compile::compile(source, &object)?;
+
+        println!("{}", object.display());
+        for modalias in &modaliases {
+            println!("  - {}", modalias.hid_device_entry());
+        }
+
+        if !install {
+            continue;
+        }
+
+        let fname = object.file_name().unwrap();
+        let stem = fname
+            .to_string_lossy()
+            .trim_end_matches(".bpf.o")
+            .to_string();
+        let hwdb = std::path::Path::new(HWDB_DIR).join(format!("99-hid-bpf-{}.hwdb", stem));
+
+        std::fs::create_dir_all(&target_bpf_dir)?;
+        std::fs::copy(&object, target_bpf_dir.join(fname))?;
+        std::fs::write(&hwdb, compile::hwdb_entries(&object, modaliases))?;
+        println!("  installed in {}", target_bpf_dir.display());
+        println!("  hwdb entries in {}", hwdb.display());
+    }
+
+    if install {
+        println!("Run 'systemd-hwdb update' and replug the device to load the new objects");
+    }
+
+    Ok(())
+}
+
+fn cmd_status(format: Format) -> std::io::Result<()> {
+    let devices = bpf::pinned_devices()?;
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&devices)?);
+        return Ok(());
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let id = |id: Option<u32>| id.map_or(String::from("?"), |id| id.to_string());
+
+    for device in devices {
+        if device.present {
+            println!("/sys/bus/hid/devices/{}", device.sysname);
+        } else {
+            println!(
+                "{} (device is gone, stale pins in {})",
+                device.sysname, device.bpffs_path
+            );
+        }
+        for object in device.objects {
+            println!("  - {} (priority {}):", object.name, object.priority);
+            for prog in object.programs {
+                let loaded = prog.load_time.map_or(String::from("?"), |t| {
+                    format!("{}s ago", now.saturating_sub(t))
+                });
+                println!(
+                    "    - program {}: prog id {}, link id {}, loaded {}",
+                    prog.name,
+                    id(prog.prog_id),
+                    id(prog.link_id),
+                    loaded
+                );
+            }
+            for map in object.maps {
+                println!("    - map {}: map id {}", map.name, id(map.map_id));
+            }
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Parses the OBJECT=PRIORITY values of `daemon --priority`
+fn parse_object_priority(value: &str) -> Result<(String, i32), String> {
+    let (object, priority) = value
+        .split_once('=')
+        .ok_or(format!("expected OBJECT=PRIORITY, got {}", value))?;
+    let priority = priority
+        .parse::<i32>()
+        .map_err(|e| format!("invalid priority {}: {}", priority, e))?;
+
+    Ok((String::from(object), priority))
+}
+
+fn daemon_add_device(
+    hid_bpf_loader: &bpf::HidBPF,
+    syspath: &std::path::Path,
+    bpfdir: &std::path::Path,
+    priorities: &std::collections::HashMap<String, i32>,
+) {
+    let dev = match hidudev::HidUdev::from_syspath(&syspath.to_path_buf()) {
+        Ok(dev) => dev,
+        Err(e) => {
+            log::warn!("Failed to access device {}: {}", syspath.display(), e);
+            return;
+        }
+    };
+
+    /* we get both add and bind for the same device, only attach once */
+    if bpf::has_bpf_objects(&dev.sysname()) {
+        log::debug!("Device {} already has BPF objects attached", dev.sysname());
+        return;
+    }
+
+    dev.load_bpf_from_directory_with(hid_bpf_loader, bpfdir, None, priorities);
+}
+
+fn cmd_doctor(bpfdir: Option<std::path::PathBuf>, format: Format) -> std::io::Result<()> {
+    let target_bpf_dir = match bpfdir {
+        Some(bpf_dir) => bpf_dir,
+        None => default_bpf_dir(),
+    };
+
+    let checks = doctor::checks(&target_bpf_dir);
+    let failed = checks.iter().filter(|check| !check.ok).count();
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&checks)?);
+    } else {
+        for check in &checks {
+            println!(
+                "[{}] {}: {}",
+                if check.ok { " ok " } else { "FAIL" },
+                check.name,
+                check.details
+            );
+            if let Some(remediation) = &check.remediation {
+                println!("       -> {}", remediation);
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(std::io::Error::other(format!("{} check(s) failed", failed)));
+    }
+
+    Ok(())
+}
+
+fn cmd_daemon(
+    bpfdir: Option<std::path::PathBuf>,
+    priority: Vec<(String, i32)>,
+) -> std::io::Result<()> {
+    let target_bpf_dir = match bpfdir {
+        Some(bpf_dir) => bpf_dir,
+        None => default_bpf_dir(),
+    };
+    let priorities: std::collections::HashMap<String, i32> = priority.into_iter().collect();
+
+    let hid_bpf_loader = bpf::HidBPF::new().map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    /* start monitoring before the coldplug so we don't miss any event in between */
+    let mut socket = udev::MonitorBuilder::new()?
+        .match_subsystem("hid")?
+        .listen()?;
+
+    let mut poll = mio::Poll::new()?;
+    let mut events = mio::Events::with_capacity(1024);
+
+    poll.registry()
+        .register(&mut socket, mio::Token(0), mio::Interest::READABLE)?;
+
+    let mut enumerator = udev::Enumerator::new()?;
+    enumerator.match_subsystem("hid")?;
+    for device in enumerator.scan_devices()? {
+        daemon_add_device(
+            &hid_bpf_loader,
+            device.syspath(),
+            &target_bpf_dir,
+            &priorities,
+        );
+    }
+
+    log::info!(
+        "Watching for HID devices, BPF objects in {}",
+        target_bpf_dir.display()
     );
-    for entry in std::fs::read_dir(dir)? {
+
+    loop {
+        if let Err(e) = poll.poll(&mut events, None) {
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+
+        for event in events.iter() {
+            if event.token() != mio::Token(0) {
+                continue;
+            }
+
+            for udev_event in socket.by_ref() {
+                log::debug!(
+                    "udev event {:?} for {}",
+                    udev_event.event_type(),
+                    udev_event.syspath().display()
+                );
+                match udev_event.event_type() {
+                    udev::EventType::Add | udev::EventType::Bind => daemon_add_device(
+                        &hid_bpf_loader,
+                        udev_event.syspath(),
+                        &target_bpf_dir,
+                        &priorities,
+                    ),
+                    udev::EventType::Remove => {
+                        if let Some(sysname) = udev_event.sysname().to_str() {
+                            if let Err(e) = bpf::remove_bpf_objects(sysname) {
+                                log::warn!(
+                                    "Failed to remove the BPF objects of {}: {}",
+                                    sysname,
+                                    e
+                                );
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+/// Attaches the object to the device. Returns whether it has an rdesc_fixup
+/// program, or None if it could not be attached.
+fn dev_attach(
+    hid_bpf_loader: &bpf::HidBPF,
+    dev: &hidudev::HidUdev,
+    object: &std::path::Path,
+) -> Option<bool> {
+    let info = match bpf::inspect_object(object) {
+        Ok(info) => info,
+        Err(e) => {
+            log::warn!("Failed to inspect {}: {}", object.display(), e);
+            return None;
+        }
+    };
+    let rdesc_fixup = info
+        .programs
+        .iter()
+        .any(|prog| prog.section.ends_with("hid_bpf_rdesc_fixup"));
+
+    match hid_bpf_loader.load_programs(&object.to_path_buf(), dev, None) {
+        Ok(true) => {
+            log::info!("{} attached to {}", object.display(), dev.sysname());
+            return Some(rdesc_fixup);
+        }
+        Ok(false) => log::warn!("{} not attached to {}", object.display(), dev.sysname()),
+        Err(e) => log::warn!("Failed to load {}: {}", object.display(), e),
+    }
+
+    /* drop whatever got pinned before the failure */
+    bpf::remove_bpf_object(&dev.sysname(), object).ok();
+
+    None
+}
+
+/// Compiles the source into the object that is not attached, attaches it and
+/// only then detaches the previous version, so a broken edit leaves the
+/// previous version in place. The device is rebound if either version has an
+/// rdesc_fixup program, so the kernel parses the report descriptor again.
+fn dev_reload(
+    hid_bpf_loader: &bpf::HidBPF,
+    dev: &hidudev::HidUdev,
+    source: &std::path::Path,
+    objects: &[std::path::PathBuf; 2],
+    attached: &mut Option<(usize, bool)>,
+) {
+    let next = match attached {
+        Some((index, _)) => 1 - *index,
+        None => 0,
+    };
+    let object = &objects[next];
+
+    if let Err(e) = compile::compile(source, object) {
+        log::warn!("{}", e);
+        return;
+    }
+
+    let mut rebind = match dev_attach(hid_bpf_loader, dev, object) {
+        Some(rdesc_fixup) => rdesc_fixup,
+        None => return,
+    };
+
+    if let Some((previous, rdesc_fixup)) = attached.replace((next, rebind)) {
+        if let Err(e) = bpf::remove_bpf_object(&dev.sysname(), &objects[previous]) {
+            log::warn!("Failed to detach {}: {}", objects[previous].display(), e);
+        }
+        rebind |= rdesc_fixup;
+    }
+
+    if rebind {
+        if let Err(e) = dev.rebind() {
+            log::warn!("Failed to rebind {}: {}", dev.sysname(), e);
+        }
+    }
+}
+
+/// Removes everything attached to the device, rebinding it if the report
+/// descriptor was fixed so the kernel goes back to the original one.
+fn dev_detach(dev: &hidudev::HidUdev, rdesc_fixup: bool) -> std::io::Result<()> {
+    bpf::remove_bpf_objects(&dev.sysname())?;
+    if rdesc_fixup {
+        dev.rebind()?;
+    }
+
+    Ok(())
+}
+
+/// Reads the pending inotify events and returns the names of the files
+fn inotify_file_names(fd: &std::os::fd::OwnedFd) -> Vec<std::ffi::OsString> {
+    use std::os::fd::AsRawFd;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut names = Vec::new();
+    let mut buffer = [0u8; 4096];
+    let header = std::mem::size_of::<libc::inotify_event>();
+
+    loop {
+        let len = unsafe {
+            libc::read(
+                fd.as_raw_fd(),
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        if len <= 0 {
+            break;
+        }
+
+        let mut offset = 0;
+        while offset + header <= len as usize {
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const _) };
+            let name = &buffer[offset + header..offset + header + event.len as usize];
+            let name = name.split(|b| *b == 0).next().unwrap_or_default();
+            names.push(std::ffi::OsStr::from_bytes(name).to_os_string());
+            offset += header + event.len as usize;
+        }
+    }
+
+    names
+}
+
+fn cmd_dev(
+    device: &std::path::Path,
+    source: &std::path::Path,
+    replace: bool,
+) -> std::io::Result<()> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+
+    let dev = hid_device(device)?;
+    let sysname = dev.sysname();
+    /* two objects, so the new version is attached before the previous one goes */
+    let object = compile::object_path(source, Some(&std::env::temp_dir()));
+    let name = object.file_stem().unwrap_or_default().to_string_lossy();
+    let name = name.trim_end_matches(".bpf");
+    let objects = [0, 1].map(|i| object.with_file_name(format!("{}-{}.bpf.o", name, i)));
+    let file_name = source.file_name().map(|n| n.to_os_string());
+    let source_dir = match source.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::path::PathBuf::from("."),
+    };
+
+    let hid_bpf_loader = bpf::HidBPF::new().map_err(|e| std::io::Error::other(e.to_string()))?;
+
+    /* editors usually write a new file and rename it, so watch the folder */
+    let inotify = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if inotify < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let inotify = unsafe { OwnedFd::from_raw_fd(inotify) };
+    let dir = std::ffi::CString::new(source_dir.as_os_str().as_bytes())?;
+    if unsafe {
+        libc::inotify_add_watch(
+            inotify.as_raw_fd(),
+            dir.as_ptr(),
+            libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO,
+        )
+    } < 0
+    {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    /* handle Ctrl-C in the loop, so we can clean up */
+    let signals = unsafe {
+        let mut mask: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut mask);
+        libc::sigaddset(&mut mask, libc::SIGINT);
+        libc::sigaddset(&mut mask, libc::SIGTERM);
+        libc::sigprocmask(libc::SIG_BLOCK, &mask, std::ptr::null_mut());
+        libc::signalfd(-1, &mask, libc::SFD_NONBLOCK | libc::SFD_CLOEXEC)
+    };
+    if signals < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let signals = unsafe { OwnedFd::from_raw_fd(signals) };
+
+    let mut poll = mio::Poll::new()?;
+    let mut events = mio::Events::with_capacity(16);
+    poll.registry().register(
+        &mut mio::unix::SourceFd(&inotify.as_raw_fd()),
+        mio::Token(0),
+        mio::Interest::READABLE,
+    )?;
+    poll.registry().register(
+        &mut mio::unix::SourceFd(&signals.as_raw_fd()),
+        mio::Token(1),
+        mio::Interest::READABLE,
+    )?;
+
+    /* start from the device as the kernel sees it, without any other program */
+    if bpf::has_bpf_objects(&sysname) {
+        if !replace {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already has BPF programs attached, use --replace to remove them",
+                    sysname
+                ),
+            ));
+        }
+        log::warn!("Removing the BPF programs already attached to {}", sysname);
+        dev_detach(&dev, true)?;
+    }
+
+    let mut attached = None;
+    dev_reload(&hid_bpf_loader, &dev, source, &objects, &mut attached);
+
+    log::info!("Watching {}, press Ctrl-C to stop", source.display());
+
+    'watch: loop {
+        if let Err(e) = poll.poll(&mut events, None) {
+            if e.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            log::error!("Failed to wait for changes: {}", e);
+            break;
+        }
+
+        let mut changed = false;
+        for event in events.iter() {
+            match event.token() {
+                mio::Token(0) => {
+                    changed |= inotify_file_names(&inotify)
+                        .iter()
+                        .any(|name| Some(name) == file_name.as_ref())
+                }
+                _ => break 'watch,
+            }
+        }
+
+        if changed {
+            log::info!("{} changed, reloading", source.display());
+            dev_reload(&hid_bpf_loader, &dev, source, &objects, &mut attached);
+        }
+    }
+
+    log::info!("Removing the BPF programs from {}", sysname);
+    for object in &objects {
+        std::fs::remove_file(object).ok();
+    }
+    dev_detach(&dev, matches!(attached, Some((_, true))))
+}
+
+#[derive(Debug, Serialize)]
+struct BpfProgramEntry {
+    file: String,
+    modaliases: Vec<bpf::ModaliasInfo>,
+}
+
+fn cmd_list_bpf_programs(
+    bpfdir: Option<std::path::PathBuf>,
+    format: Format,
+) -> std::io::Result<()> {
+    let dir = bpfdir.or(Some(default_bpf_dir())).unwrap();
+    let mut entries = Vec::new();
+
+    for entry in std::fs::read_dir(&dir)? {
         if let Ok(entry) = entry {
             let fname = entry.file_name();
             let name = fname.to_string_lossy();
             if name.ends_with(".bpf.o") {
-                println!(" {name}");
+                entries.push(BpfProgramEntry {
+                    file: name.to_string(),
+                    modaliases: bpf::object_modaliases(&entry.path()).unwrap_or_default(),
+                });
             }
         }
     }
 
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    println!(
+        "Showing available BPF files in {}:",
+        dir.as_path().to_str().unwrap()
+    );
+    for entry in entries {
+        println!(" {}", entry.file);
+    }
+
     Ok(())
 }
 
-fn cmd_list_devices() -> std::io::Result<()> {
-    let re = Regex::new(r"hid:b([A-Z0-9]{4})g([A-Z0-9]{4})v0000([A-Z0-9]{4})p0000([A-Z0-9]{4})")
-        .unwrap();
+#[derive(Debug, Serialize)]
+struct DeviceEntry {
+    syspath: String,
+    name: String,
+    bus: String,
+    group: String,
+    vid: u32,
+    pid: u32,
+    hid_device: String,
+    /// the BPF objects whose metadata match this device
+    objects: Vec<DeviceObject>,
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceObject {
+    file: String,
+    /// whether the object is currently pinned for this device in the bpffs
+    attached: bool,
+}
+
+fn cmd_list_devices(bpfdir: Option<std::path::PathBuf>, format: Format) -> std::io::Result<()> {
+    let bpf_objects = bpf::objects_metadata(&bpfdir.unwrap_or_else(default_bpf_dir))?;
+    let mut devices = Vec::new();
 
     // We use this path because it looks nicer than the true device path in /sys/devices/pci...
     for entry in std::fs::read_dir("/sys/bus/hid/devices")? {
         let syspath = entry.unwrap().path();
-        let device = udev::Device::from_syspath(&syspath)?;
-        let name = device.property_value("HID_NAME").unwrap().to_str().unwrap();
-        if let Some(Some(matches)) = device
-            .property_value("MODALIAS")
-            .map(|modalias| re.captures(modalias.to_str().unwrap()))
-        {
-            let bus = matches.get(1).unwrap().as_str();
-            let group = matches.get(2).unwrap().as_str();
-            let vid = matches.get(3).unwrap().as_str();
-            let pid = matches.get(4).unwrap().as_str();
-
-            let bus = match bus {
-                "0001" => "BUS_PCI",
-                "0002" => "BUS_ISAPNP",
-                "0003" => "BUS_USB",
-                "0004" => "BUS_HIL",
-                "0005" => "BUS_BLUETOOTH",
-                "0006" => "BUS_VIRTUAL",
-                "0010" => "BUS_ISA",
-                "0011" => "BUS_I8042",
-                "0012" => "BUS_XTKBD",
-                "0013" => "BUS_RS232",
-                "0014" => "BUS_GAMEPORT",
-                "0015" => "BUS_PARPORT",
-                "0016" => "BUS_AMIGA",
-                "0017" => "BUS_ADB",
-                "0018" => "BUS_I2C",
-                "0019" => "BUS_HOST",
-                "001A" => "BUS_GSC",
-                "001B" => "BUS_ATARI",
-                "001C" => "BUS_SPI",
-                "001D" => "BUS_RMI",
-                "001E" => "BUS_CEC",
-                "001F" => "BUS_INTEL_ISHTP",
-                "0020" => "BUS_AMD_SFH",
-                _ => bus,
-            };
+        /* the device may be gone since we listed the folder */
+        let device = match udev::Device::from_syspath(&syspath) {
+            Ok(device) => device,
+            Err(e) => {
+                log::debug!("Skipping {}: {}", syspath.display(), e);
+                continue;
+            }
+        };
+        let name = device
+            .property_value("HID_NAME")
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
 
-            let group = match group {
-                "0001" => "HID_GROUP_GENERIC",
-                "0002" => "HID_GROUP_MULTITOUCH",
-                "0003" => "HID_GROUP_SENSOR_HUB",
-                "0004" => "HID_GROUP_MULTITOUCH_WIN_8",
-                "0100" => "HID_GROUP_RMI",
-                "0101" => "HID_GROUP_WACOM",
-                "0102" => "HID_GROUP_LOGITECH_DJ_DEVICE",
-                "0103" => "HID_GROUP_STEAM",
-                "0104" => "HID_GROUP_LOGITECH_27MHZ_DEVICE",
-                "0105" => "HID_GROUP_VIVALDI",
-                _ => group,
+        if let Ok(modalias) = modalias::Modalias::from_udev_device(&device) {
+            let sysname = device.sysname().to_string_lossy().to_string();
+            let hid_device = match hidudev::HidUdev::from_syspath(&syspath) {
+                Ok(hid_device) => hid_device,
+                Err(e) => {
+                    log::warn!("Failed to access device {}: {}", syspath.display(), e);
+                    continue;
+                }
             };
+            let objects = bpf_objects
+                .iter()
+                .filter(|(_, metadata)| metadata.iter().any(|m| hid_device.entry_matches(m)))
+                .map(|(path, _)| {
+                    let object_name = path.file_stem().unwrap().to_string_lossy();
+                    DeviceObject {
+                        file: path.file_name().unwrap().to_string_lossy().to_string(),
+                        attached: std::path::Path::new(&bpf::get_bpffs_path(
+                            &sysname,
+                            &object_name,
+                        ))
+                        .exists(),
+                    }
+                })
+                .collect();
 
-            println!("{}", syspath.to_str().unwrap());
-            println!("  - name: {name}");
-            println!("  - device entry: HID_DEVICE({bus}, {group}, 0x{vid}, 0x{pid})");
-            println!("");
+            devices.push(DeviceEntry {
+                syspath: syspath.to_string_lossy().to_string(),
+                name,
+                bus: modalias.bus.to_string(),
+                group: modalias.group.to_string(),
+                vid: modalias.vid,
+                pid: modalias.pid,
+                hid_device: modalias.hid_device_entry(),
+                objects,
+            });
         }
     }
+
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&devices)?);
+        return Ok(());
+    }
+
+    for device in devices {
+        println!("{}", device.syspath);
+        println!("  - name: {}", device.name);
+        println!("  - device entry: {}", device.hid_device);
+        if device.objects.is_empty() {
+            println!("  - bpf objects: none");
+        } else {
+            println!("  - bpf objects:");
+        }
+        for object in device.objects {
+            if object.attached {
+                println!("    - {} (attached)", object.file);
+            } else {
+                println!("    - {} (not attached)", object.file);
+            }
+        }
+        println!();
+    }
     Ok(())
 }
 
@@ -222,10 +1565,44 @@ fn main() -> std::io::Result<()> {
             devpath,
             prog,
             bpfdir,
-        } => cmd_add(&devpath, prog, bpfdir),
+            priority,
+        } => cmd_add(&devpath, prog, bpfdir, priority),
         Commands::Remove { devpath } => cmd_remove(&devpath),
-        Commands::ListBpfPrograms { bpfdir } => cmd_list_bpf_programs(bpfdir),
-        Commands::ListDevices {} => cmd_list_devices(),
+        Commands::ListBpfPrograms { bpfdir } => cmd_list_bpf_programs(bpfdir, cli.format),
+        Commands::ListDevices { bpfdir } => cmd_list_devices(bpfdir, cli.format),
+        Commands::Inspect { object } => cmd_inspect(&object, cli.format),
+        Commands::Verify { objects } => cmd_verify(&objects, cli.format),
+        Commands::Match {
+            devpath,
+            prog,
+            bpfdir,
+        } => cmd_match(&devpath, prog, bpfdir, cli.format),
+        Commands::Probe {
+            object,
+            rdesc,
+            hid_id,
+        } => cmd_probe(&object, &rdesc, hid_id),
+        Commands::Rdesc { device, c_array } => cmd_rdesc(&device, c_array, cli.format),
+        Commands::RdescDiff { device, original } => cmd_rdesc_diff(&device, original, cli.format),
+        Commands::New {
+            device,
+            identifier,
+            output_dir,
+        } => cmd_new(&device, &identifier, &output_dir),
+        Commands::Compile {
+            sources,
+            output_dir,
+            install,
+            bpfdir,
+        } => cmd_compile(&sources, output_dir, install, bpfdir),
+        Commands::Dev {
+            device,
+            source,
+            replace,
+        } => cmd_dev(&device, &source, replace),
+        Commands::Status {} => cmd_status(cli.format),
+        Commands::Doctor { bpfdir } => cmd_doctor(bpfdir, cli.format),
+        Commands::Daemon { bpfdir, priority } => cmd_daemon(bpfdir, priority),
     }
 }
 
@@ -254,4 +1631,68 @@ mod tests {
             assert!(sysname.is_ok());
         }
     }
+
+    #[test]
+    fn test_bpf_source_template() {
+        let mut modalias =
+            modalias::Modalias::from_static_str("b0003g0001v000004D9p0000A09F").unwrap();
+        modalias.name = Some(String::from("Holtek USB Gaming Mouse"));
+        let rdesc = [
+            0x05, 0x01, // Usage Page (Generic Desktop)
+            0x09, 0x02, // Usage (Mouse)
+            0xa1, 0x01, // Collection (Application)
+            0x85, 0x02, //  Report ID (2)
+            0x75, 0x08, //  Report Size (8)
+            0x95, 0x03, //  Report Count (3)
+            0x81, 0x02, //  Input (Data,Var,Abs)
+            0xc0, // End Collection
+        ];
+
+        let source = bpf_source_template("G10-Mouse", &modalias, &rdesc).unwrap();
+
+        assert!(source.contains("/* Holtek USB Gaming Mouse */"));
+        assert!(source.contains(
+            "\tHID_DEVICE_RDESC_SIZE(BUS_USB, HID_GROUP_GENERIC, 0x04D9, 0xA09F, 15, 15)\n"
+        ));
+        assert!(source.contains("int BPF_PROG(G10_Mouse_fix_rdesc, struct hid_bpf_ctx *hctx)"));
+        assert!(source.contains("int BPF_PROG(G10_Mouse_fix_event, struct hid_bpf_ctx *hctx)"));
+        assert!(source.contains("hid_bpf_get_data(hctx, 0 /* offset */, 4 /* size */)"));
+        assert!(source.contains("\tif (ctx->rdesc_size != 15)\n\t\tctx->retval = -EINVAL;\n"));
+
+        /* the name can not close the comment it is in */
+        modalias.name = Some(String::from("Evil */ #error\n"));
+        let source = bpf_source_template("G10-Mouse", &modalias, &rdesc).unwrap();
+        assert!(source.contains("/* Evil * / #error */"));
+        assert!(source.contains(
+            " * 0x85, 0x02,                    //  Report ID (2)                      6\n"
+        ));
+    }
+
+    #[test]
+    fn test_valid_identifier() {
+        assert!(valid_identifier("G10-Mechanical_Gaming-Mouse"));
+        assert!(!valid_identifier(""));
+        assert!(!valid_identifier("../G10"));
+        assert!(!valid_identifier("/tmp/G10"));
+        assert!(!valid_identifier("G10.bpf"));
+    }
+
+    #[test]
+    fn test_rdesc_file_parsing() {
+        let raw = vec![0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0xc0];
+        let rdesc = parse_rdesc_data(raw.clone());
+        assert!(rdesc.unwrap() == raw);
+
+        let recording = b"# Foo Bar\nR: 7 05 01 09 02 a1 01 c0\nN: Foo Bar\n".to_vec();
+        let rdesc = parse_rdesc_data(recording);
+        assert!(rdesc.unwrap() == raw);
+
+        let recording = b"R: 8 05 01 09 02 a1 01 c0\n".to_vec();
+        let rdesc = parse_rdesc_data(recording);
+        assert!(rdesc.is_err());
+
+        let recording = b"R: 7 05 01 09 02 a1 01 zz\n".to_vec();
+        let rdesc = parse_rdesc_data(recording);
+        assert!(rdesc.is_err());
+    }
 }
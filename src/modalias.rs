@@ -28,6 +28,8 @@ pub enum Bus {
     CEC,
     IntelIshtp,
     AmdSfh,
+    /// Any bus value we don't know about
+    Other(u16),
 }
 
 impl TryFrom<usize> for Bus {
@@ -59,7 +61,9 @@ impl TryFrom<usize> for Bus {
             0x1E => Ok(Bus::CEC),
             0x1F => Ok(Bus::IntelIshtp),
             0x20 => Ok(Bus::AmdSfh),
-            _ => Err("Invalid bus type"),
+            _ => u16::try_from(sz)
+                .map(Bus::Other)
+                .map_err(|_| "Invalid bus type"),
         }
     }
 }
@@ -91,6 +95,7 @@ impl From<&Bus> for usize {
             Bus::CEC => 0x1E,
             Bus::IntelIshtp => 0x1F,
             Bus::AmdSfh => 0x20,
+            Bus::Other(bus) => *bus as usize,
         }
     }
 }
@@ -117,6 +122,8 @@ pub enum Group {
     Steam,
     Logitech27mhz,
     Vivaldi,
+    /// Any group value we don't know about, e.g. a new vendor group
+    Other(u16),
 }
 
 impl TryFrom<usize> for Group {
@@ -135,7 +142,9 @@ impl TryFrom<usize> for Group {
             0x0103 => Ok(Group::Steam),
             0x0104 => Ok(Group::Logitech27mhz),
             0x0105 => Ok(Group::Vivaldi),
-            _ => Err("Invalid group type"),
+            _ => u16::try_from(sz)
+                .map(Group::Other)
+                .map_err(|_| "Invalid group type"),
         }
     }
 }
@@ -154,6 +163,7 @@ impl From<&Group> for usize {
             Group::Steam => 0x0103,
             Group::Logitech27mhz => 0x0104,
             Group::Vivaldi => 0x0105,
+            Group::Other(group) => *group as usize,
         }
     }
 }
@@ -195,6 +205,61 @@ impl std::fmt::LowerHex for Group {
     }
 }
 
+/// Prints the bus as its define in hid_bpf_helpers.h, e.g. `BUS_USB`
+impl std::fmt::Display for Bus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Bus::Any => "BUS_ANY",
+            Bus::PCI => "BUS_PCI",
+            Bus::ISAPnP => "BUS_ISAPNP",
+            Bus::USB => "BUS_USB",
+            Bus::HIL => "BUS_HIL",
+            Bus::Bluetooth => "BUS_BLUETOOTH",
+            Bus::Virtual => "BUS_VIRTUAL",
+            Bus::ISA => "BUS_ISA",
+            Bus::I8042 => "BUS_I8042",
+            Bus::XtKbd => "BUS_XTKBD",
+            Bus::Rs232 => "BUS_RS232",
+            Bus::GamePort => "BUS_GAMEPORT",
+            Bus::ParPort => "BUS_PARPORT",
+            Bus::Amiga => "BUS_AMIGA",
+            Bus::ADB => "BUS_ADB",
+            Bus::I2C => "BUS_I2C",
+            Bus::Host => "BUS_HOST",
+            Bus::GSC => "BUS_GSC",
+            Bus::Atari => "BUS_ATARI",
+            Bus::SPI => "BUS_SPI",
+            Bus::RMI => "BUS_RMI",
+            Bus::CEC => "BUS_CEC",
+            Bus::IntelIshtp => "BUS_INTEL_ISHTP",
+            Bus::AmdSfh => "BUS_AMD_SFH",
+            Bus::Other(bus) => return write!(f, "0x{:04X}", bus),
+        };
+        f.write_str(name)
+    }
+}
+
+/// Prints the group as its define in hid_bpf_helpers.h, e.g. `HID_GROUP_GENERIC`
+impl std::fmt::Display for Group {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Group::Any => "HID_GROUP_ANY",
+            Group::Generic => "HID_GROUP_GENERIC",
+            Group::Multitouch => "HID_GROUP_MULTITOUCH",
+            Group::SensorHub => "HID_GROUP_SENSOR_HUB",
+            Group::MultitouchWin8 => "HID_GROUP_MULTITOUCH_WIN_8",
+            Group::RMI => "HID_GROUP_RMI",
+            Group::Wacom => "HID_GROUP_WACOM",
+            Group::LogitechDJ => "HID_GROUP_LOGITECH_DJ_DEVICE",
+            Group::Steam => "HID_GROUP_STEAM",
+            Group::Logitech27mhz => "HID_GROUP_LOGITECH_27MHZ_DEVICE",
+            Group::Vivaldi => "HID_GROUP_VIVALDI",
+            Group::Other(group) => return write!(f, "0x{:04X}", group),
+        };
+        f.write_str(name)
+    }
+}
+
 pub struct Metadata<'m> {
     btf: &'m libbpf_rs::btf::Btf<'m>,
     types: BtfTypes::Union<'m>,
@@ -236,12 +301,121 @@ impl<'m> Metadata<'m> {
     }
 }
 
+/// Options that apply to all the programs of an object, declared through
+/// HID_BPF_OPTIONS() next to HID_BPF_CONFIG()
+#[derive(Debug, Default, PartialEq)]
+pub struct ObjectOptions {
+    /// The programs of the object work without each other, so the ones that
+    /// could be attached are kept when another one fails
+    pub programs_independent: bool,
+    /// Objects with a higher priority process the events first
+    pub priority: i32,
+}
+
+/// Added to the priority in the metadata, see HID_BPF_PRIORITY() in hid_bpf_helpers.h
+const PRIORITY_BIAS: i64 = 0x10000;
+
+impl ObjectOptions {
+    pub fn from_btf(btf: &libbpf_rs::btf::Btf) -> Self {
+        let mut options = ObjectOptions::default();
+
+        let datasec = match btf.type_by_name::<BtfTypes::DataSec>(".hid_bpf_config") {
+            Some(datasec) => datasec,
+            None => return options,
+        };
+
+        for var_sec_info in datasec.iter() {
+            let var = match btf.type_by_id::<BtfTypes::Var>(var_sec_info.ty) {
+                Some(var) => var,
+                None => continue,
+            };
+
+            if var.name().and_then(|name| name.to_str().ok()) != Some("_options") {
+                continue;
+            }
+
+            let var_type = var.referenced_type().skip_mods_and_typedefs();
+            let options_struct = match BtfTypes::Struct::try_from(var_type) {
+                Ok(options_struct) => options_struct,
+                Err(_) => continue,
+            };
+
+            for member in options_struct.iter() {
+                let member_name = member.name.and_then(|name| name.to_str().ok());
+                let array = btf
+                    .type_by_id::<BtfTypes::Ptr>(member.ty)
+                    .map(|pointer| BtfTypes::Array::try_from(pointer.referenced_type()));
+
+                log::debug!(target:"HID-BPF metadata", "option {:?}", member);
+
+                if let (Some(member_name), Some(Ok(array))) = (member_name, array) {
+                    match member_name {
+                        "programs_independent" => {
+                            options.programs_independent = array.capacity() != 0
+                        }
+                        "priority" => {
+                            options.priority = (array.capacity() as i64 - PRIORITY_BIAS) as i32
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+
+        options
+    }
+}
+
 #[derive(Debug, Hash, Eq, PartialEq)]
 pub struct Modalias {
     pub bus: Bus,
     pub group: Group,
     pub vid: u32,
     pub pid: u32,
+    /// `HID_NAME` of the device, or a glob pattern for it in the metadata
+    pub name: Option<String>,
+    /// `HID_UNIQ` of the device, or a glob pattern for it in the metadata
+    pub uniq: Option<String>,
+    /// `HID_PHYS` of the device, or a glob pattern for it in the metadata
+    pub phys: Option<String>,
+    /// Expected report descriptor size range, metadata only
+    pub rdesc_size_min: Option<usize>,
+    pub rdesc_size_max: Option<usize>,
+    /// Expected SHA-256 of the report descriptor in hex, metadata only
+    pub rdesc_sha256: Option<String>,
+    /// Required top-level application collection usage page, metadata only
+    pub usage_page: Option<u32>,
+    /// Required top-level application collection usage, any usage of
+    /// `usage_page` if unset, metadata only
+    pub usage: Option<u32>,
+}
+
+/// Minimal glob matching: '*' matches any sequence of characters, '?' any
+/// single character.
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while v < value.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == value[v]) {
+            p += 1;
+            v += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, v));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            /* let the last '*' eat one more character and retry */
+            p = star + 1;
+            v = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
 }
 
 impl Modalias {
@@ -251,6 +425,14 @@ impl Modalias {
             group: Group::Any,
             vid: 0,
             pid: 0,
+            name: None,
+            uniq: None,
+            phys: None,
+            rdesc_size_min: None,
+            rdesc_size_max: None,
+            rdesc_sha256: None,
+            usage_page: None,
+            usage: None,
         }
     }
 
@@ -261,21 +443,74 @@ impl Modalias {
         let device_descr = btf.type_by_id::<BtfTypes::Struct>(union_member.ty)?;
         let mut modalias = Modalias::new();
 
-        for member in device_descr.iter() {
-            let member_name = String::from(member.name.unwrap().to_str().unwrap());
+        /* name, uniq and phys patterns are stored as decl tags on the struct members */
+        let tags: Vec<(u32, String)> = btf
+            .type_by_kind::<BtfTypes::DeclTag>()
+            .filter(|tag| tag.referenced_type().type_id() == device_descr.type_id())
+            .filter_map(|tag| {
+                let value = tag.name()?.to_str().ok()?;
+                Some((tag.component_index()?, String::from(value)))
+            })
+            .collect();
+
+        for (idx, member) in device_descr.iter().enumerate() {
+            let member_name = match member.name.and_then(|name| name.to_str().ok()) {
+                Some(name) => String::from(name),
+                None => continue,
+            };
             log::debug!(target:"HID-BPF metadata", "    -> {:?}", member);
+
+            if let Some(pattern) = tags
+                .iter()
+                .filter(|(component, _)| *component as usize == idx)
+                .find_map(|(_, tag)| tag.strip_prefix(&format!("{}=", member_name)))
+                .filter(|pattern| !pattern.is_empty())
+            {
+                log::debug!(target:"HID-BPF metadata", "      -> {:?}: {:?}", member_name, pattern);
+                match member_name.as_str() {
+                    "name" => modalias.name = Some(String::from(pattern)),
+                    "uniq" => modalias.uniq = Some(String::from(pattern)),
+                    "phys" => modalias.phys = Some(String::from(pattern)),
+                    "rdesc_sha256" => modalias.rdesc_sha256 = Some(pattern.to_lowercase()),
+                    _ => (),
+                }
+            }
+
             if let Some(Ok(array)) = btf
                 .type_by_id::<BtfTypes::Ptr>(member.ty)
                 .map(|pointer| BtfTypes::Array::try_from(pointer.referenced_type()))
             {
-                match member_name.as_str() {
-                    "bus" => modalias.bus = Bus::try_from(array.capacity()).unwrap(),
-                    "group" => modalias.group = Group::try_from(array.capacity()).unwrap(),
-                    "vid" => modalias.vid = u32::try_from(array.capacity()).unwrap(),
-                    "pid" => modalias.pid = u32::try_from(array.capacity()).unwrap(),
-                    _ => (),
+                let value = array.capacity();
+                let parsed = match member_name.as_str() {
+                    "bus" => Bus::try_from(value).map(|bus| modalias.bus = bus),
+                    "group" => Group::try_from(value).map(|group| modalias.group = group),
+                    "vid" => u32::try_from(value)
+                        .map(|vid| modalias.vid = vid)
+                        .map_err(|_| "Invalid vendor ID"),
+                    "pid" => u32::try_from(value)
+                        .map(|pid| modalias.pid = pid)
+                        .map_err(|_| "Invalid product ID"),
+                    "rdesc_size_min" => {
+                        modalias.rdesc_size_min = Some(value);
+                        Ok(())
+                    }
+                    "rdesc_size_max" => {
+                        modalias.rdesc_size_max = Some(value);
+                        Ok(())
+                    }
+                    "usage_page" => u32::try_from(value)
+                        .map(|usage_page| modalias.usage_page = Some(usage_page))
+                        .map_err(|_| "Invalid usage page"),
+                    "usage" => u32::try_from(value)
+                        .map(|usage| modalias.usage = Some(usage).filter(|u| *u != 0))
+                        .map_err(|_| "Invalid usage"),
+                    _ => Ok(()),
+                };
+                log::debug!(target:"HID-BPF metadata", "      -> {:?}: {:#06X}", member_name, value);
+                if let Err(e) = parsed {
+                    log::warn!(target:"HID-BPF metadata", "ignoring entry: {} {:#06X}", e, value);
+                    return None;
                 }
-                log::debug!(target:"HID-BPF metadata", "      -> {:?}: {:#06X}", member_name, array.capacity());
             }
         }
         Some(modalias)
@@ -299,10 +534,20 @@ impl Modalias {
             )
         };
 
-        let bus =
-            Bus::try_from(usize::from_str_radix(&modalias[1..5], 16).map_err(econvert)?).unwrap();
-        let group = Group::try_from(usize::from_str_radix(&modalias[6..10], 16).map_err(econvert)?)
-            .unwrap();
+        let bus = usize::from_str_radix(&modalias[1..5], 16).map_err(econvert)?;
+        let bus = Bus::try_from(bus).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid modalias '{}': {}", modalias, e),
+            )
+        })?;
+        let group = usize::from_str_radix(&modalias[6..10], 16).map_err(econvert)?;
+        let group = Group::try_from(group).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid modalias '{}': {}", modalias, e),
+            )
+        })?;
         let vid = u32::from_str_radix(&modalias[11..19], 16).map_err(econvert)?;
         let pid = u32::from_str_radix(&modalias[20..28], 16).map_err(econvert)?;
 
@@ -311,9 +556,105 @@ impl Modalias {
             group,
             vid,
             pid,
+            name: None,
+            uniq: None,
+            phys: None,
+            rdesc_size_min: None,
+            rdesc_size_max: None,
+            rdesc_sha256: None,
+            usage_page: None,
+            usage: None,
         })
     }
 
+    /// Whether this modalias, as found in the metadata of a BPF object, matches
+    /// the given device. `Bus::Any`, `Group::Any` and a vid or pid of 0 act as
+    /// wildcards, the name, uniq and phys are glob patterns if present.
+    pub fn matches(&self, device: &Modalias) -> bool {
+        let pattern_matches = |pattern: &Option<String>, value: &Option<String>| match pattern {
+            None => true,
+            Some(pattern) => value.as_ref().is_some_and(|v| glob_matches(pattern, v)),
+        };
+
+        (self.bus == Bus::Any || self.bus == device.bus)
+            && (self.group == Group::Any || self.group == device.group)
+            && (self.vid == 0 || self.vid == device.vid)
+            && (self.pid == 0 || self.pid == device.pid)
+            && pattern_matches(&self.name, &device.name)
+            && pattern_matches(&self.uniq, &device.uniq)
+            && pattern_matches(&self.phys, &device.phys)
+    }
+
+    /// Whether this metadata entry has a report descriptor size or hash
+    /// requirement, i.e. needs [`Modalias::matches_rdesc`]
+    pub fn has_rdesc_fingerprint(&self) -> bool {
+        self.rdesc_size_min.is_some()
+            || self.rdesc_size_max.is_some()
+            || self.rdesc_sha256.is_some()
+    }
+
+    /// Whether the report descriptor fingerprint of this metadata entry, if
+    /// any, matches a report descriptor of the given size and SHA-256 (hex).
+    pub fn matches_rdesc(&self, rdesc_size: usize, rdesc_sha256: &str) -> bool {
+        let too_small = self.rdesc_size_min.is_some_and(|min| rdesc_size < min);
+        let too_big = self.rdesc_size_max.is_some_and(|max| rdesc_size > max);
+        let wrong_hash = self
+            .rdesc_sha256
+            .as_ref()
+            .is_some_and(|sha256| !sha256.eq_ignore_ascii_case(rdesc_sha256));
+
+        !(too_small || too_big || wrong_hash)
+    }
+
+    /// Whether the device exposes the top-level application collection this
+    /// metadata entry requires, if any. `applications` are the usages
+    /// (usage page << 16 | usage) of the top-level application collections
+    /// of the device.
+    pub fn matches_applications(&self, applications: &[u32]) -> bool {
+        let usage_page = match self.usage_page {
+            Some(usage_page) => usage_page,
+            None => return true,
+        };
+
+        applications.iter().any(|application| {
+            let usage_matches = match self.usage {
+                Some(usage) => application & 0xFFFF == usage,
+                None => true,
+            };
+            application >> 16 == usage_page && usage_matches
+        })
+    }
+
+    /// The bus, group, vid and pid arguments of the `HID_DEVICE*()` macros
+    fn hid_device_arguments(&self) -> String {
+        let vid = match self.vid {
+            0 => String::from("HID_VID_ANY"),
+            _ => format!("0x{:04X}", self.vid),
+        };
+        let pid = match self.pid {
+            0 => String::from("HID_PID_ANY"),
+            _ => format!("0x{:04X}", self.pid),
+        };
+
+        format!("{}, {}, {}, {}", self.bus, self.group, vid, pid)
+    }
+
+    /// The `HID_DEVICE()` entry for `HID_BPF_CONFIG` matching this modalias
+    pub fn hid_device_entry(&self) -> String {
+        format!("HID_DEVICE({})", self.hid_device_arguments())
+    }
+
+    /// The `HID_DEVICE_RDESC_SIZE()` entry for `HID_BPF_CONFIG` matching this
+    /// modalias with a report descriptor of exactly `rdesc_size` bytes
+    pub fn hid_device_rdesc_size_entry(&self, rdesc_size: usize) -> String {
+        format!(
+            "HID_DEVICE_RDESC_SIZE({}, {}, {})",
+            self.hid_device_arguments(),
+            rdesc_size,
+            rdesc_size
+        )
+    }
+
     pub fn from_static_str(modalias: &'static str) -> std::io::Result<Self> {
         Self::from_str(&modalias)
     }
@@ -328,10 +669,26 @@ impl Modalias {
 
         let modalias = match modalias.to_str() {
             Some(data) => data,
-            _ => panic!("modalias problem"),
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Invalid modalias {:?}", modalias),
+                ))
+            }
         };
 
-        Self::from_str(modalias)
+        let property = |name: &str| {
+            udev_device
+                .property_value(name)
+                .map(|value| value.to_string_lossy().to_string())
+        };
+
+        let mut modalias = Self::from_str(modalias)?;
+        modalias.name = property("HID_NAME");
+        modalias.uniq = property("HID_UNIQ");
+        modalias.phys = property("HID_PHYS");
+
+        Ok(modalias)
     }
 }
 
@@ -355,3 +712,193 @@ impl From<Modalias> for String {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modalias(bus: Bus, group: Group, vid: u32, pid: u32) -> Modalias {
+        Modalias {
+            bus,
+            group,
+            vid,
+            pid,
+            name: None,
+            uniq: None,
+            phys: None,
+            rdesc_size_min: None,
+            rdesc_size_max: None,
+            rdesc_sha256: None,
+            usage_page: None,
+            usage: None,
+        }
+    }
+
+    #[test]
+    fn test_matches_exact() {
+        let device = Modalias::from_static_str("b0003g0001v000004D9p0000A09F").unwrap();
+
+        assert!(modalias(Bus::USB, Group::Generic, 0x04D9, 0xA09F).matches(&device));
+        assert!(!modalias(Bus::Bluetooth, Group::Generic, 0x04D9, 0xA09F).matches(&device));
+        assert!(!modalias(Bus::USB, Group::Multitouch, 0x04D9, 0xA09F).matches(&device));
+        assert!(!modalias(Bus::USB, Group::Generic, 0x046D, 0xA09F).matches(&device));
+        assert!(!modalias(Bus::USB, Group::Generic, 0x04D9, 0xC52B).matches(&device));
+    }
+
+    #[test]
+    fn test_matches_wildcards() {
+        let device = Modalias::from_static_str("b0003g0001v000004D9p0000A09F").unwrap();
+
+        assert!(modalias(Bus::Any, Group::Generic, 0x04D9, 0xA09F).matches(&device));
+        assert!(modalias(Bus::USB, Group::Any, 0x04D9, 0xA09F).matches(&device));
+        assert!(modalias(Bus::USB, Group::Generic, 0, 0xA09F).matches(&device));
+        assert!(modalias(Bus::USB, Group::Generic, 0x04D9, 0).matches(&device));
+        assert!(modalias(Bus::USB, Group::Any, 0x04D9, 0).matches(&device));
+        assert!(modalias(Bus::Any, Group::Any, 0, 0).matches(&device));
+
+        /* a wildcard doesn't make the other fields optional */
+        assert!(!modalias(Bus::Any, Group::Generic, 0x046D, 0xA09F).matches(&device));
+        assert!(!modalias(Bus::USB, Group::Any, 0x04D9, 0xC52B).matches(&device));
+        assert!(!modalias(Bus::I2C, Group::Any, 0, 0).matches(&device));
+        assert!(!modalias(Bus::Any, Group::Wacom, 0, 0).matches(&device));
+        assert!(!modalias(Bus::Any, Group::Any, 0x046D, 0).matches(&device));
+        assert!(!modalias(Bus::Any, Group::Any, 0, 0xC52B).matches(&device));
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob_matches("", ""));
+        assert!(!glob_matches("", "foo"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*", "foo"));
+        assert!(glob_matches("foo", "foo"));
+        assert!(!glob_matches("foo", "foobar"));
+        assert!(glob_matches("foo*", "foobar"));
+        assert!(glob_matches("*bar", "foobar"));
+        assert!(glob_matches("f*b*r", "foobar"));
+        assert!(glob_matches("f?o*", "foobar"));
+        assert!(!glob_matches("f?o", "fo"));
+        assert!(glob_matches("*o*o*", "foo bar boo"));
+        assert!(!glob_matches("*x*", "foo bar boo"));
+        assert!(glob_matches(
+            "UGTABLET 24 inch*",
+            "UGTABLET 24 inch PenDisplay"
+        ));
+    }
+
+    #[test]
+    fn test_matches_name_uniq_phys() {
+        let mut device = Modalias::from_static_str("b0003g0001v000028BDp0000093A").unwrap();
+        device.name = Some(String::from("UGTABLET 24 inch PenDisplay"));
+        device.uniq = Some(String::from("0000001"));
+        device.phys = Some(String::from("usb-0000:00:14.0-1/input0"));
+
+        let mut entry = modalias(Bus::USB, Group::Generic, 0x28BD, 0x093A);
+        assert!(entry.matches(&device));
+
+        entry.name = Some(String::from("UGTABLET 24*"));
+        assert!(entry.matches(&device));
+
+        entry.phys = Some(String::from("*/input1"));
+        assert!(!entry.matches(&device));
+
+        entry.phys = Some(String::from("*/input0"));
+        assert!(entry.matches(&device));
+
+        entry.uniq = Some(String::from("1234"));
+        assert!(!entry.matches(&device));
+
+        /* a pattern never matches a device without that property */
+        device.uniq = None;
+        entry.uniq = Some(String::from("*"));
+        assert!(!entry.matches(&device));
+    }
+
+    #[test]
+    fn test_matches_rdesc() {
+        let sha256 = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef";
+        let mut entry = modalias(Bus::USB, Group::Generic, 0x04D9, 0xA09F);
+
+        /* no fingerprint, anything goes */
+        assert!(!entry.has_rdesc_fingerprint());
+        assert!(entry.matches_rdesc(71, sha256));
+
+        entry.rdesc_size_min = Some(71);
+        entry.rdesc_size_max = Some(71);
+        assert!(entry.has_rdesc_fingerprint());
+        assert!(entry.matches_rdesc(71, sha256));
+        assert!(!entry.matches_rdesc(70, sha256));
+        assert!(!entry.matches_rdesc(72, sha256));
+
+        entry.rdesc_size_max = Some(100);
+        assert!(entry.matches_rdesc(100, sha256));
+        assert!(!entry.matches_rdesc(101, sha256));
+
+        entry.rdesc_size_min = None;
+        entry.rdesc_size_max = None;
+        entry.rdesc_sha256 = Some(sha256.to_uppercase());
+        assert!(entry.has_rdesc_fingerprint());
+        assert!(entry.matches_rdesc(71, sha256));
+        assert!(!entry.matches_rdesc(71, &sha256.replace('0', "f")));
+    }
+
+    #[test]
+    fn test_matches_applications() {
+        let applications = [0x000D0002, 0x00010006];
+        let mut entry = modalias(Bus::Any, Group::Any, 0, 0);
+
+        /* no usage required, anything goes */
+        assert!(entry.matches_applications(&[]));
+
+        entry.usage_page = Some(0x0D);
+        assert!(entry.matches_applications(&applications));
+        assert!(!entry.matches_applications(&[0x00010006]));
+
+        entry.usage = Some(0x02);
+        assert!(entry.matches_applications(&applications));
+
+        entry.usage = Some(0x04);
+        assert!(!entry.matches_applications(&applications));
+        assert!(!entry.matches_applications(&[]));
+    }
+
+    #[test]
+    fn test_hid_device_entry() {
+        let m = Modalias::from_static_str("b0003g0001v000004D9p0000A09F").unwrap();
+        assert!(m.hid_device_entry() == "HID_DEVICE(BUS_USB, HID_GROUP_GENERIC, 0x04D9, 0xA09F)");
+
+        let m = Modalias::from_static_str("b0018g0000v00000000p00000000").unwrap();
+        assert!(
+            m.hid_device_entry() == "HID_DEVICE(BUS_I2C, HID_GROUP_ANY, HID_VID_ANY, HID_PID_ANY)"
+        );
+    }
+
+    #[test]
+    fn test_unknown_bus_and_group() {
+        let m = Modalias::from_static_str("b0021g0106v000004D9p0000A09F").unwrap();
+        assert!(m.bus == Bus::Other(0x21));
+        assert!(m.group == Group::Other(0x106));
+        assert!(m.hid_device_entry() == "HID_DEVICE(0x0021, 0x0106, 0x04D9, 0xA09F)");
+        assert!(format!("{:x}/{:x}", m.bus, m.group) == "21/106");
+        assert!(String::from(m) == "b0021g0106v000004D9p0000A09F");
+
+        /* known values never end up as Other */
+        assert!(Bus::try_from(0x03).unwrap() == Bus::USB);
+        assert!(Group::try_from(0x0101).unwrap() == Group::Wacom);
+        assert!(usize::from(&Bus::Other(0x42)) == 0x42);
+        assert!(usize::from(&Group::Other(0x0200)) == 0x0200);
+
+        assert!(Bus::try_from(0x10000).is_err());
+        assert!(Group::try_from(0x10000).is_err());
+    }
+
+    #[test]
+    fn test_matches_is_not_symmetric() {
+        let device = Modalias::from_static_str("b0003g0001v000004D9p0000A09F").unwrap();
+        let entry = modalias(Bus::Any, Group::Any, 0, 0);
+
+        /* wildcards are only meaningful in the metadata entry */
+        assert!(entry.matches(&device));
+        assert!(!device.matches(&entry));
+    }
+}
@@ -0,0 +1,1172 @@
+// SPDX-License-Identifier: GPL-2.0-only
+
+//! HID report descriptor parsing, see the "Device Class Definition for HID",
+//! section 6.2.2.
+
+/// The type of a short item, bits 2-3 of the item prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemType {
+    Main,
+    Global,
+    Local,
+    /// reserved short items and long items
+    Reserved,
+}
+
+/* Main item tags */
+pub const INPUT: u8 = 0x8;
+pub const OUTPUT: u8 = 0x9;
+pub const COLLECTION: u8 = 0xA;
+pub const FEATURE: u8 = 0xB;
+pub const END_COLLECTION: u8 = 0xC;
+
+/* Global item tags */
+pub const USAGE_PAGE: u8 = 0x0;
+pub const LOGICAL_MINIMUM: u8 = 0x1;
+pub const LOGICAL_MAXIMUM: u8 = 0x2;
+pub const PHYSICAL_MINIMUM: u8 = 0x3;
+pub const PHYSICAL_MAXIMUM: u8 = 0x4;
+pub const UNIT_EXPONENT: u8 = 0x5;
+pub const UNIT: u8 = 0x6;
+pub const REPORT_SIZE: u8 = 0x7;
+pub const REPORT_ID: u8 = 0x8;
+pub const REPORT_COUNT: u8 = 0x9;
+pub const PUSH: u8 = 0xA;
+pub const POP: u8 = 0xB;
+
+/* Local item tags */
+pub const USAGE: u8 = 0x0;
+pub const USAGE_MINIMUM: u8 = 0x1;
+pub const USAGE_MAXIMUM: u8 = 0x2;
+
+/// The collection type of an Application collection
+pub const COLLECTION_APPLICATION: u32 = 0x01;
+
+/// Bit 0 of the data of Input, Output and Feature items
+pub const FIELD_CONSTANT: u32 = 0x01;
+
+const LONG_ITEM_PREFIX: u8 = 0xFE;
+
+/// A single item of a report descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    /// offset of the item prefix in the report descriptor
+    pub offset: usize,
+    pub item_type: ItemType,
+    pub tag: u8,
+    /// the item data, little endian
+    pub data: Vec<u8>,
+    /// the whole item as found in the report descriptor
+    pub raw: Vec<u8>,
+}
+
+impl Item {
+    /// The data of the item as an unsigned value
+    pub fn value(&self) -> u32 {
+        self.data
+            .iter()
+            .rev()
+            .fold(0, |value, byte| (value << 8) | *byte as u32)
+    }
+
+    /// The data of the item as a sign-extended value
+    pub fn signed_value(&self) -> i32 {
+        match self.data.len() {
+            1 => self.value() as u8 as i8 as i32,
+            2 => self.value() as u16 as i16 as i32,
+            _ => self.value() as i32,
+        }
+    }
+}
+
+fn invalid(offset: usize, msg: &str) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("{} at offset {}", msg, offset),
+    )
+}
+
+/// Splits a report descriptor into its items
+pub fn items(rdesc: &[u8]) -> std::io::Result<Vec<Item>> {
+    let mut items = Vec::new();
+    let mut offset = 0;
+
+    while offset < rdesc.len() {
+        let prefix = rdesc[offset];
+        let truncated = || invalid(offset, "Truncated report descriptor item");
+
+        let item = if prefix == LONG_ITEM_PREFIX {
+            let header = rdesc.get(offset + 1..offset + 3).ok_or_else(truncated)?;
+            let size = header[0] as usize;
+            let data = rdesc
+                .get(offset + 3..offset + 3 + size)
+                .ok_or_else(truncated)?;
+            Item {
+                offset,
+                item_type: ItemType::Reserved,
+                tag: header[1],
+                data: data.to_vec(),
+                raw: rdesc[offset..offset + 3 + size].to_vec(),
+            }
+        } else {
+            let size = match prefix & 0x3 {
+                3 => 4,
+                size => size as usize,
+            };
+            let data = rdesc
+                .get(offset + 1..offset + 1 + size)
+                .ok_or_else(truncated)?;
+            Item {
+                offset,
+                item_type: match (prefix >> 2) & 0x3 {
+                    0 => ItemType::Main,
+                    1 => ItemType::Global,
+                    2 => ItemType::Local,
+                    _ => ItemType::Reserved,
+                },
+                tag: prefix >> 4,
+                data: data.to_vec(),
+                raw: rdesc[offset..offset + 1 + size].to_vec(),
+            }
+        };
+
+        offset += item.raw.len();
+        items.push(item);
+    }
+
+    Ok(items)
+}
+
+/// Input, Output or Feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+impl std::fmt::Display for ReportKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReportKind::Input => write!(f, "Input"),
+            ReportKind::Output => write!(f, "Output"),
+            ReportKind::Feature => write!(f, "Feature"),
+        }
+    }
+}
+
+/// The global items in effect, saved and restored by Push and Pop
+#[derive(Debug, Clone, Default)]
+struct GlobalState {
+    usage_page: u32,
+    logical_minimum: i32,
+    logical_maximum: i32,
+    physical_minimum: i32,
+    physical_maximum: i32,
+    unit_exponent: u32,
+    unit: u32,
+    report_size: u32,
+    report_id: Option<u8>,
+    report_count: u32,
+}
+
+/// The maximum of a range is unsigned when the minimum is not negative
+/// (Logical Maximum (255) is often encoded as 0x25 0xff)
+fn maximum(minimum: i32, maximum: &Item) -> i32 {
+    if minimum >= 0 && maximum.data.len() < 4 {
+        maximum.value() as i32
+    } else {
+        maximum.signed_value()
+    }
+}
+
+/// A field of a report, created by one Input, Output or Feature item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// offset of the main item in the report descriptor
+    pub offset: usize,
+    pub kind: ReportKind,
+    /// the data of the main item (Data/Constant, Array/Variable, ...)
+    pub flags: u32,
+    pub usage_page: u32,
+    /// the usages (usage page << 16 | usage) as inclusive (minimum, maximum)
+    /// ranges, a single Usage being a range of one. Usage Minimum/Maximum are
+    /// not expanded, they can cover billions of extended usages.
+    pub usage_ranges: Vec<(u32, u32)>,
+    pub logical_minimum: i32,
+    pub logical_maximum: i32,
+    pub physical_minimum: i32,
+    pub physical_maximum: i32,
+    pub unit_exponent: u32,
+    pub unit: u32,
+    pub report_id: Option<u8>,
+    pub report_size: u32,
+    pub report_count: u32,
+    /// bit offset of the field in the report, including the report ID byte
+    pub bit_offset: u32,
+}
+
+impl Field {
+    pub fn is_constant(&self) -> bool {
+        self.flags & FIELD_CONSTANT != 0
+    }
+
+    /// The size of the field in bits
+    pub fn bits(&self) -> u32 {
+        self.report_size * self.report_count
+    }
+
+    /// Whether `usage` (usage page << 16 | usage) is one of the usages of the field
+    pub fn has_usage(&self, usage: u32) -> bool {
+        self.usage_ranges
+            .iter()
+            .any(|(minimum, maximum)| (*minimum..=*maximum).contains(&usage))
+    }
+
+    /// The usages of the field, in order, Usage Minimum/Maximum expanded
+    pub fn usages(&self) -> impl Iterator<Item = u32> + '_ {
+        self.usage_ranges
+            .iter()
+            .flat_map(|(minimum, maximum)| *minimum..=*maximum)
+    }
+}
+
+/// All fields with the same kind and report ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub kind: ReportKind,
+    pub report_id: Option<u8>,
+    pub fields: Vec<Field>,
+}
+
+impl Report {
+    /// The size of the report in bits, including the report ID byte
+    pub fn bits(&self) -> u32 {
+        let report_id = if self.report_id.is_some() { 8 } else { 0 };
+
+        report_id + self.fields.iter().map(Field::bits).sum::<u32>()
+    }
+}
+
+/// A collection with its nested collections and fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Collection {
+    /// offset of the Collection item in the report descriptor
+    pub offset: usize,
+    pub collection_type: u32,
+    /// the usage (usage page << 16 | usage) of the collection, if any
+    pub usage: Option<u32>,
+    pub collections: Vec<Collection>,
+    pub fields: Vec<Field>,
+}
+
+/// A parsed report descriptor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportDescriptor {
+    pub items: Vec<Item>,
+    /// the top-level collections
+    pub collections: Vec<Collection>,
+    /// the reports, in order of first appearance
+    pub reports: Vec<Report>,
+}
+
+impl ReportDescriptor {
+    pub fn parse(rdesc: &[u8]) -> std::io::Result<Self> {
+        let items = items(rdesc)?;
+        let mut globals = GlobalState::default();
+        let mut globals_stack: Vec<GlobalState> = Vec::new();
+        let mut usage_ranges: Vec<(u32, u32)> = Vec::new();
+        /* Usage Minimum and Usage Maximum, which can come in any order */
+        let mut usage_bounds: (Option<u32>, Option<u32>) = (None, None);
+        let mut collections: Vec<Collection> = Vec::new();
+        /* the currently open collections, innermost last */
+        let mut open: Vec<Collection> = Vec::new();
+        let mut reports: Vec<Report> = Vec::new();
+        let mut last_offset = 0;
+
+        let extended = |usage_page: u32, item: &Item| match item.data.len() {
+            /* a 4 bytes usage already carries its usage page */
+            4 => item.value(),
+            _ => (usage_page << 16) | item.value(),
+        };
+
+        for item in &items {
+            match (item.item_type, item.tag) {
+                (ItemType::Global, USAGE_PAGE) => globals.usage_page = item.value(),
+                (ItemType::Global, LOGICAL_MINIMUM) => {
+                    globals.logical_minimum = item.signed_value()
+                }
+                (ItemType::Global, LOGICAL_MAXIMUM) => {
+                    globals.logical_maximum = maximum(globals.logical_minimum, item)
+                }
+                (ItemType::Global, PHYSICAL_MINIMUM) => {
+                    globals.physical_minimum = item.signed_value()
+                }
+                (ItemType::Global, PHYSICAL_MAXIMUM) => {
+                    globals.physical_maximum = maximum(globals.physical_minimum, item)
+                }
+                (ItemType::Global, UNIT_EXPONENT) => globals.unit_exponent = item.value(),
+                (ItemType::Global, UNIT) => globals.unit = item.value(),
+                (ItemType::Global, REPORT_SIZE) => globals.report_size = item.value(),
+                (ItemType::Global, REPORT_ID) => globals.report_id = Some(item.value() as u8),
+                (ItemType::Global, REPORT_COUNT) => globals.report_count = item.value(),
+                (ItemType::Global, PUSH) => globals_stack.push(globals.clone()),
+                (ItemType::Global, POP) => {
+                    globals = globals_stack
+                        .pop()
+                        .ok_or_else(|| invalid(item.offset, "Pop without Push"))?
+                }
+                (ItemType::Local, USAGE) => {
+                    let usage = extended(globals.usage_page, item);
+                    usage_ranges.push((usage, usage))
+                }
+                (ItemType::Local, USAGE_MINIMUM | USAGE_MAXIMUM) => {
+                    let usage = Some(extended(globals.usage_page, item));
+                    match item.tag {
+                        USAGE_MINIMUM => usage_bounds.0 = usage,
+                        _ => usage_bounds.1 = usage,
+                    }
+                    if let (Some(minimum), Some(maximum)) = usage_bounds {
+                        usage_ranges.push((minimum, maximum));
+                        usage_bounds = (None, None);
+                    }
+                }
+                (ItemType::Main, COLLECTION) => open.push(Collection {
+                    offset: item.offset,
+                    collection_type: item.value(),
+                    usage: usage_ranges.first().map(|(minimum, _)| *minimum),
+                    collections: Vec::new(),
+                    fields: Vec::new(),
+                }),
+                (ItemType::Main, END_COLLECTION) => {
+                    let collection = open
+                        .pop()
+                        .ok_or_else(|| invalid(item.offset, "End Collection without Collection"))?;
+                    match open.last_mut() {
+                        Some(parent) => parent.collections.push(collection),
+                        None => collections.push(collection),
+                    }
+                }
+                (ItemType::Main, INPUT | OUTPUT | FEATURE) => {
+                    let kind = match item.tag {
+                        INPUT => ReportKind::Input,
+                        OUTPUT => ReportKind::Output,
+                        _ => ReportKind::Feature,
+                    };
+                    let report = match reports
+                        .iter_mut()
+                        .position(|r| r.kind == kind && r.report_id == globals.report_id)
+                    {
+                        Some(idx) => &mut reports[idx],
+                        None => {
+                            reports.push(Report {
+                                kind,
+                                report_id: globals.report_id,
+                                fields: Vec::new(),
+                            });
+                            reports.last_mut().unwrap()
+                        }
+                    };
+                    let field = Field {
+                        offset: item.offset,
+                        kind,
+                        flags: item.value(),
+                        usage_page: globals.usage_page,
+                        usage_ranges: usage_ranges.clone(),
+                        logical_minimum: globals.logical_minimum,
+                        logical_maximum: globals.logical_maximum,
+                        physical_minimum: globals.physical_minimum,
+                        physical_maximum: globals.physical_maximum,
+                        unit_exponent: globals.unit_exponent,
+                        unit: globals.unit,
+                        report_id: globals.report_id,
+                        report_size: globals.report_size,
+                        report_count: globals.report_count,
+                        bit_offset: report.bits(),
+                    };
+                    report.fields.push(field.clone());
+                    if let Some(collection) = open.last_mut() {
+                        collection.fields.push(field);
+                    }
+                }
+                _ => {}
+            }
+
+            /* local items only apply to the next main item */
+            if item.item_type == ItemType::Main {
+                usage_ranges.clear();
+                usage_bounds = (None, None);
+            }
+            last_offset = item.offset;
+        }
+
+        if !open.is_empty() {
+            return Err(invalid(last_offset, "Unterminated Collection"));
+        }
+
+        Ok(ReportDescriptor {
+            items,
+            collections,
+            reports,
+        })
+    }
+
+    /// Returns the usages (usage page << 16 | usage) of the top-level
+    /// Application collections
+    pub fn application_usages(&self) -> Vec<u32> {
+        self.collections
+            .iter()
+            .filter(|c| c.collection_type == COLLECTION_APPLICATION)
+            .filter_map(|c| c.usage)
+            .collect()
+    }
+}
+
+/// Returns the usages (usage page << 16 | usage) of the top-level
+/// Application collections of a report descriptor
+pub fn application_usages(rdesc: &[u8]) -> std::io::Result<Vec<u32>> {
+    Ok(ReportDescriptor::parse(rdesc)?.application_usages())
+}
+
+/* Local item tags only used for annotating */
+const DESIGNATOR_INDEX: u8 = 0x3;
+const DESIGNATOR_MINIMUM: u8 = 0x4;
+const DESIGNATOR_MAXIMUM: u8 = 0x5;
+const STRING_INDEX: u8 = 0x7;
+const STRING_MINIMUM: u8 = 0x8;
+const STRING_MAXIMUM: u8 = 0x9;
+const DELIMITER: u8 = 0xA;
+
+fn usage_page_name(usage_page: u32) -> String {
+    let name = match usage_page {
+        0x01 => "Generic Desktop",
+        0x02 => "Simulation Controls",
+        0x03 => "VR Controls",
+        0x04 => "Sport Controls",
+        0x05 => "Game Controls",
+        0x06 => "Generic Device Controls",
+        0x07 => "Keyboard",
+        0x08 => "LED",
+        0x09 => "Button",
+        0x0A => "Ordinal",
+        0x0B => "Telephony",
+        0x0C => "Consumer",
+        0x0D => "Digitizers",
+        0x0E => "Haptics",
+        0x0F => "Physical Input Device",
+        0x10 => "Unicode",
+        0x12 => "Eye and Head Trackers",
+        0x14 => "Auxiliary Display",
+        0x20 => "Sensors",
+        0x40 => "Medical Instrument",
+        0x41 => "Braille Display",
+        0x59 => "Lighting And Illumination",
+        0x80 => "Monitor",
+        0x84 => "Power Device",
+        0x85 => "Battery System",
+        0x8C => "Bar Code Scanner",
+        0x8D => "Scale",
+        0x90 => "Camera Control",
+        0x91 => "Arcade",
+        0xF1D0 => "FIDO Alliance",
+        0xFF00..=0xFFFF => return format!("Vendor Defined Page 0x{:04X}", usage_page),
+        _ => return format!("0x{:04X}", usage_page),
+    };
+    String::from(name)
+}
+
+fn usage_name(usage_page: u32, usage: u32) -> String {
+    let name = match (usage_page, usage) {
+        (0x01, 0x01) => "Pointer",
+        (0x01, 0x02) => "Mouse",
+        (0x01, 0x04) => "Joystick",
+        (0x01, 0x05) => "Game Pad",
+        (0x01, 0x06) => "Keyboard",
+        (0x01, 0x07) => "Keypad",
+        (0x01, 0x08) => "Multi-axis Controller",
+        (0x01, 0x09) => "Tablet PC System Controls",
+        (0x01, 0x0E) => "System Multi-Axis Controller",
+        (0x01, 0x30) => "X",
+        (0x01, 0x31) => "Y",
+        (0x01, 0x32) => "Z",
+        (0x01, 0x33) => "Rx",
+        (0x01, 0x34) => "Ry",
+        (0x01, 0x35) => "Rz",
+        (0x01, 0x36) => "Slider",
+        (0x01, 0x37) => "Dial",
+        (0x01, 0x38) => "Wheel",
+        (0x01, 0x39) => "Hat switch",
+        (0x01, 0x3D) => "Start",
+        (0x01, 0x3E) => "Select",
+        (0x01, 0x48) => "Resolution Multiplier",
+        (0x01, 0x80) => "System Control",
+        (0x01, 0x81) => "System Power Down",
+        (0x01, 0x82) => "System Sleep",
+        (0x01, 0x83) => "System Wake Up",
+        (0x01, 0x90) => "D-pad Up",
+        (0x01, 0x91) => "D-pad Down",
+        (0x01, 0x92) => "D-pad Right",
+        (0x01, 0x93) => "D-pad Left",
+        (0x09, 0x00) => "No Buttons Pressed",
+        (0x09, _) => return format!("Button {}", usage),
+        (0x0C, 0x01) => "Consumer Control",
+        (0x0C, 0x02) => "Numeric Key Pad",
+        (0x0C, 0x03) => "Programmable Buttons",
+        (0x0C, 0x30) => "Power",
+        (0x0C, 0x40) => "Menu",
+        (0x0C, 0x6F) => "Display Brightness Increment",
+        (0x0C, 0x70) => "Display Brightness Decrement",
+        (0x0C, 0x81) => "Assign Selection",
+        (0x0C, 0x99) => "Media Select Security",
+        (0x0C, 0xB0) => "Play",
+        (0x0C, 0xB1) => "Pause",
+        (0x0C, 0xB2) => "Record",
+        (0x0C, 0xB3) => "Fast Forward",
+        (0x0C, 0xB4) => "Rewind",
+        (0x0C, 0xB5) => "Scan Next Track",
+        (0x0C, 0xB6) => "Scan Previous Track",
+        (0x0C, 0xB7) => "Stop",
+        (0x0C, 0xB8) => "Eject",
+        (0x0C, 0xCD) => "Play/Pause",
+        (0x0C, 0xE0) => "Volume",
+        (0x0C, 0xE2) => "Mute",
+        (0x0C, 0xE9) => "Volume Increment",
+        (0x0C, 0xEA) => "Volume Decrement",
+        (0x0C, 0x183) => "AL Consumer Control Configuration",
+        (0x0C, 0x18A) => "AL Email Reader",
+        (0x0C, 0x192) => "AL Calculator",
+        (0x0C, 0x194) => "AL Local Machine Browser",
+        (0x0C, 0x221) => "AC Search",
+        (0x0C, 0x223) => "AC Home",
+        (0x0C, 0x224) => "AC Back",
+        (0x0C, 0x225) => "AC Forward",
+        (0x0C, 0x226) => "AC Stop",
+        (0x0C, 0x227) => "AC Refresh",
+        (0x0C, 0x22A) => "AC Bookmarks",
+        (0x0C, 0x238) => "AC Pan",
+        (0x0D, 0x01) => "Digitizer",
+        (0x0D, 0x02) => "Pen",
+        (0x0D, 0x03) => "Light Pen",
+        (0x0D, 0x04) => "Touch Screen",
+        (0x0D, 0x05) => "Touch Pad",
+        (0x0D, 0x06) => "Whiteboard",
+        (0x0D, 0x0E) => "Device Configuration",
+        (0x0D, 0x20) => "Stylus",
+        (0x0D, 0x21) => "Puck",
+        (0x0D, 0x22) => "Finger",
+        (0x0D, 0x23) => "Device Settings",
+        (0x0D, 0x30) => "Tip Pressure",
+        (0x0D, 0x31) => "Barrel Pressure",
+        (0x0D, 0x32) => "In Range",
+        (0x0D, 0x33) => "Touch",
+        (0x0D, 0x34) => "Untouch",
+        (0x0D, 0x35) => "Tap",
+        (0x0D, 0x36) => "Quality",
+        (0x0D, 0x37) => "Data Valid",
+        (0x0D, 0x38) => "Transducer Index",
+        (0x0D, 0x39) => "Tablet Function Keys",
+        (0x0D, 0x3A) => "Program Change Keys",
+        (0x0D, 0x3B) => "Battery Strength",
+        (0x0D, 0x3C) => "Invert",
+        (0x0D, 0x3D) => "X Tilt",
+        (0x0D, 0x3E) => "Y Tilt",
+        (0x0D, 0x3F) => "Azimuth",
+        (0x0D, 0x40) => "Altitude",
+        (0x0D, 0x41) => "Twist",
+        (0x0D, 0x42) => "Tip Switch",
+        (0x0D, 0x43) => "Secondary Tip Switch",
+        (0x0D, 0x44) => "Barrel Switch",
+        (0x0D, 0x45) => "Eraser",
+        (0x0D, 0x46) => "Tablet Pick",
+        (0x0D, 0x47) => "Confidence",
+        (0x0D, 0x48) => "Width",
+        (0x0D, 0x49) => "Height",
+        (0x0D, 0x51) => "Contact Id",
+        (0x0D, 0x52) => "Device Mode",
+        (0x0D, 0x53) => "Device Identifier",
+        (0x0D, 0x54) => "Contact Count",
+        (0x0D, 0x55) => "Contact Count Maximum",
+        (0x0D, 0x56) => "Scan Time",
+        (0x0D, 0x57) => "Surface Switch",
+        (0x0D, 0x58) => "Button Switch",
+        (0x0D, 0x59) => "Pad Type",
+        (0x0D, 0x5A) => "Secondary Barrel Switch",
+        (0x0D, 0x5B) => "Transducer Serial Number",
+        (0x0D, 0x5C) => "Preferred Color",
+        _ => return format!("0x{:04X}", usage),
+    };
+    String::from(name)
+}
+
+fn collection_name(collection_type: u32) -> String {
+    let name = match collection_type {
+        0x00 => "Physical",
+        0x01 => "Application",
+        0x02 => "Logical",
+        0x03 => "Report",
+        0x04 => "Named Array",
+        0x05 => "Usage Switch",
+        0x06 => "Usage Modifier",
+        _ => return format!("Vendor Defined 0x{:02X}", collection_type),
+    };
+    String::from(name)
+}
+
+/// "Data,Var,Abs" and the non-default bits of Input, Output and Feature items
+fn main_item_flags(flags: u32) -> String {
+    let mut names = vec![
+        if flags & 0x01 != 0 { "Cnst" } else { "Data" },
+        if flags & 0x02 != 0 { "Var" } else { "Arr" },
+        if flags & 0x04 != 0 { "Rel" } else { "Abs" },
+    ];
+    for (bit, name) in [
+        (0x08, "Wrap"),
+        (0x10, "NonLin"),
+        (0x20, "NoPref"),
+        (0x40, "Null"),
+        (0x80, "Vol"),
+        (0x100, "Buff"),
+    ] {
+        if flags & bit != 0 {
+            names.push(name);
+        }
+    }
+    names.join(",")
+}
+
+/// A 4 bits two's complement value, as used by Unit and Unit Exponent
+fn nibble(value: u32) -> i32 {
+    let value = (value & 0xF) as i32;
+    if value > 7 {
+        value - 16
+    } else {
+        value
+    }
+}
+
+fn unit_name(unit: u32) -> String {
+    let (system, names) = match unit & 0xF {
+        0x0 => return String::from("None"),
+        0x1 => ("SILinear", ["cm", "g", "s", "K", "A", "cd"]),
+        0x2 => ("SIRotation", ["rad", "g", "s", "K", "A", "cd"]),
+        0x3 => ("EnglishLinear", ["in", "slug", "s", "F", "A", "cd"]),
+        0x4 => ("EnglishRotation", ["deg", "slug", "s", "F", "A", "cd"]),
+        _ => return format!("0x{:08X}", unit),
+    };
+    let units: Vec<String> = names
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, name)| match nibble(unit >> (4 * (idx + 1))) {
+            0 => None,
+            1 => Some(String::from(*name)),
+            exponent => Some(format!("{}^{}", name, exponent)),
+        })
+        .collect();
+    format!("{}: {}", system, units.join(" * "))
+}
+
+/// A report descriptor item with its decoded meaning
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnotatedItem {
+    pub item: Item,
+    /// the number of collections this item is nested in
+    pub depth: usize,
+    pub description: String,
+}
+
+impl AnnotatedItem {
+    /// One line of a C array, in the style of the fixed_rdesc comments:
+    /// `0x05, 0x0d,                    // Usage Page (Digitizers)             0`
+    pub fn line(&self) -> String {
+        let bytes: String = self
+            .item
+            .raw
+            .iter()
+            .map(|b| format!("0x{:02x}, ", b))
+            .collect();
+        let description = format!("{}{}", " ".repeat(self.depth), self.description);
+
+        format!(
+            "{:<31}// {:<35} {}",
+            bytes.trim_end(),
+            description,
+            self.item.offset
+        )
+    }
+}
+
+/// Decodes every item of a report descriptor
+pub fn annotate(rdesc: &[u8]) -> std::io::Result<Vec<AnnotatedItem>> {
+    let mut annotated = Vec::new();
+    let mut usage_page = 0;
+    /* needed to know if the maximums are signed */
+    let mut minimums = (0, 0);
+    let mut stack = Vec::new();
+    let mut depth: usize = 0;
+
+    for item in items(rdesc)? {
+        let value = item.value();
+        let mut item_depth = depth;
+        let description = match (item.item_type, item.tag) {
+            (ItemType::Main, INPUT) => format!("Input ({})", main_item_flags(value)),
+            (ItemType::Main, OUTPUT) => format!("Output ({})", main_item_flags(value)),
+            (ItemType::Main, FEATURE) => format!("Feature ({})", main_item_flags(value)),
+            (ItemType::Main, COLLECTION) => {
+                depth += 1;
+                format!("Collection ({})", collection_name(value))
+            }
+            (ItemType::Main, END_COLLECTION) => {
+                depth = depth.saturating_sub(1);
+                item_depth = depth;
+                String::from("End Collection")
+            }
+            (ItemType::Global, USAGE_PAGE) => {
+                usage_page = value;
+                format!("Usage Page ({})", usage_page_name(value))
+            }
+            (ItemType::Global, LOGICAL_MINIMUM) => {
+                minimums.0 = item.signed_value();
+                format!("Logical Minimum ({})", minimums.0)
+            }
+            (ItemType::Global, LOGICAL_MAXIMUM) => {
+                format!("Logical Maximum ({})", maximum(minimums.0, &item))
+            }
+            (ItemType::Global, PHYSICAL_MINIMUM) => {
+                minimums.1 = item.signed_value();
+                format!("Physical Minimum ({})", minimums.1)
+            }
+            (ItemType::Global, PHYSICAL_MAXIMUM) => {
+                format!("Physical Maximum ({})", maximum(minimums.1, &item))
+            }
+            (ItemType::Global, UNIT_EXPONENT) => format!("Unit Exponent ({})", nibble(value)),
+            (ItemType::Global, UNIT) => format!("Unit ({})", unit_name(value)),
+            (ItemType::Global, REPORT_SIZE) => format!("Report Size ({})", value),
+            (ItemType::Global, REPORT_ID) => format!("Report ID ({})", value),
+            (ItemType::Global, REPORT_COUNT) => format!("Report Count ({})", value),
+            (ItemType::Global, PUSH) => {
+                stack.push((usage_page, minimums));
+                String::from("Push")
+            }
+            (ItemType::Global, POP) => {
+                if let Some((page, mins)) = stack.pop() {
+                    usage_page = page;
+                    minimums = mins;
+                }
+                String::from("Pop")
+            }
+            (ItemType::Local, USAGE) => match item.data.len() {
+                4 => format!(
+                    "Usage ({}.{})",
+                    usage_page_name(value >> 16),
+                    usage_name(value >> 16, value & 0xFFFF)
+                ),
+                _ => format!("Usage ({})", usage_name(usage_page, value)),
+            },
+            (ItemType::Local, USAGE_MINIMUM) => format!("Usage Minimum ({})", value),
+            (ItemType::Local, USAGE_MAXIMUM) => format!("Usage Maximum ({})", value),
+            (ItemType::Local, DESIGNATOR_INDEX) => format!("Designator Index ({})", value),
+            (ItemType::Local, DESIGNATOR_MINIMUM) => format!("Designator Minimum ({})", value),
+            (ItemType::Local, DESIGNATOR_MAXIMUM) => format!("Designator Maximum ({})", value),
+            (ItemType::Local, STRING_INDEX) => format!("String Index ({})", value),
+            (ItemType::Local, STRING_MINIMUM) => format!("String Minimum ({})", value),
+            (ItemType::Local, STRING_MAXIMUM) => format!("String Maximum ({})", value),
+            (ItemType::Local, DELIMITER) => match value {
+                1 => String::from("Delimiter (Open)"),
+                _ => String::from("Delimiter (Close)"),
+            },
+            (ItemType::Reserved, _) if item.raw[0] == LONG_ITEM_PREFIX => {
+                format!("Long Item (tag 0x{:02x})", item.tag)
+            }
+            _ => format!("Unknown (0x{:02x})", item.raw[0]),
+        };
+
+        annotated.push(AnnotatedItem {
+            item,
+            depth: item_depth,
+            description,
+        });
+    }
+
+    Ok(annotated)
+}
+
+/// Formats a report descriptor as a C array ready to be pasted in a .bpf.c
+pub fn c_array(name: &str, rdesc: &[u8]) -> std::io::Result<String> {
+    let mut array = format!("static const __u8 {}[] = {{\n", name);
+    for item in annotate(rdesc)? {
+        array.push_str(&format!("\t{}\n", item.line()));
+    }
+    array.push_str("};\n");
+
+    Ok(array)
+}
+
+/// One line of an item-level diff between two report descriptors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// the item is in both descriptors, the annotation is the one of the second
+    Same(AnnotatedItem),
+    Removed(AnnotatedItem),
+    Added(AnnotatedItem),
+}
+
+impl DiffLine {
+    pub fn item(&self) -> &AnnotatedItem {
+        match self {
+            DiffLine::Same(item) | DiffLine::Removed(item) | DiffLine::Added(item) => item,
+        }
+    }
+
+    /// The line in the style of `diff -u`, prefixed with ' ', '-' or '+'
+    pub fn line(&self) -> String {
+        let prefix = match self {
+            DiffLine::Same(_) => ' ',
+            DiffLine::Removed(_) => '-',
+            DiffLine::Added(_) => '+',
+        };
+        format!("{}{}", prefix, self.item().line())
+    }
+}
+
+/// Compares two report descriptors item by item (longest common subsequence
+/// of the raw items), e.g. the original one of a device and the one after an
+/// rdesc_fixup program was applied
+pub fn diff(original: &[u8], fixed: &[u8]) -> std::io::Result<Vec<DiffLine>> {
+    let original = annotate(original)?;
+    let fixed = annotate(fixed)?;
+    let (n, m) = (original.len(), fixed.len());
+
+    /* lcs[i][j] is the LCS length of original[i..] and fixed[j..] */
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if original[i].item.raw == fixed[j].item.raw {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && original[i].item.raw == fixed[j].item.raw {
+            lines.push(DiffLine::Same(fixed[j].clone()));
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            lines.push(DiffLine::Added(fixed[j].clone()));
+            j += 1;
+        } else {
+            lines.push(DiffLine::Removed(original[i].clone()));
+            i += 1;
+        }
+    }
+
+    /* show the removed lines before the added ones of the same hunk */
+    let mut start = 0;
+    while start < lines.len() {
+        let end = start
+            + lines[start..]
+                .iter()
+                .take_while(|l| !matches!(l, DiffLine::Same(_)))
+                .count();
+        lines[start..end].sort_by_key(|l| matches!(l, DiffLine::Added(_)));
+        start = end + 1;
+    }
+
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Extracts the bytes of `const __u8 <name>[] = { ... };` from a .bpf.c
+    /// source, skipping comments (including commented out bytes)
+    fn rdesc_from_bpf_source(source: &str, name: &str) -> Vec<u8> {
+        let start = source.find(&format!("{}[] = {{", name)).unwrap();
+        let body = &source[start..];
+        let body = &body[body.find('{').unwrap() + 1..body.find("};").unwrap()];
+        let mut bytes = Vec::new();
+
+        let mut body = String::from(body);
+        while let Some(start) = body.find("/*") {
+            let end = start + body[start..].find("*/").unwrap() + 2;
+            body.replace_range(start..end, "");
+        }
+        for line in body.lines() {
+            let line = line.split("//").next().unwrap();
+            for byte in line.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                bytes.push(u8::from_str_radix(byte.trim_start_matches("0x"), 16).unwrap());
+            }
+        }
+
+        bytes
+    }
+
+    fn field_by_usage(report: &Report, usage: u32) -> &Field {
+        report.fields.iter().find(|f| f.has_usage(usage)).unwrap()
+    }
+
+    #[test]
+    fn test_items() {
+        let rdesc = [
+            0x05, 0x01, // Usage Page (Generic Desktop)
+            0x26, 0xff, 0x7f, // Logical Maximum (32767)
+            0x15, 0x81, // Logical Minimum (-127)
+            0xc0, // End Collection
+        ];
+        let items = items(&rdesc).unwrap();
+
+        assert!(items.len() == 4);
+        assert!(items[0].item_type == ItemType::Global);
+        assert!(items[0].tag == USAGE_PAGE);
+        assert!(items[0].value() == 0x01);
+        assert!(items[1].offset == 2);
+        assert!(items[1].value() == 32767);
+        assert!(items[2].signed_value() == -127);
+        assert!(items[3].item_type == ItemType::Main);
+        assert!(items[3].tag == END_COLLECTION);
+        assert!(items[3].data.is_empty());
+
+        assert!(super::items(&[0x26, 0xff]).is_err());
+    }
+
+    #[test]
+    fn test_application_usages() {
+        let rdesc = [
+            0x05, 0x0d, // Usage Page (Digitizers)
+            0x09, 0x02, // Usage (Pen)
+            0xa1, 0x01, // Collection (Application)
+            0x09, 0x20, //  Usage (Stylus)
+            0xa1, 0x01, //  Collection (Application), not top-level
+            0xc0, //  End Collection
+            0xc0, // End Collection
+            0x0b, 0x06, 0x00, 0x01, 0x00, // Usage (Generic Desktop.Keyboard)
+            0xa1, 0x01, // Collection (Application)
+            0xc0, // End Collection
+            0x09, 0x04, // Usage (Touch Screen)
+            0xa1, 0x02, // Collection (Logical)
+            0xc0, // End Collection
+        ];
+
+        assert!(application_usages(&rdesc).unwrap() == vec![0x000d0002, 0x00010006]);
+    }
+
+    #[test]
+    fn test_annotate() {
+        let source = include_str!("bpf/xppen-Artist24.bpf.c");
+        let rdesc = rdesc_from_bpf_source(source, "fixed_rdesc");
+        let annotated = annotate(&rdesc).unwrap();
+
+        /* the shipped comments were generated in the same style */
+        let expected: Vec<&str> = source
+            .lines()
+            .skip_while(|l| !l.contains("fixed_rdesc[] = {"))
+            .skip(1)
+            .take_while(|l| !l.starts_with("};"))
+            .map(|l| l.trim().split("  /*").next().unwrap())
+            .collect();
+        assert!(annotated.len() == expected.len());
+        for (item, line) in annotated.iter().zip(expected) {
+            assert!(item.line() == line, "{:?} != {:?}", item.line(), line);
+        }
+
+        assert!(unit_name(0x13) == "EnglishLinear: in");
+        assert!(unit_name(0xE011) == "SILinear: cm * s^-2");
+        assert!(main_item_flags(0x42) == "Data,Var,Abs,Null");
+
+        let array = c_array("fixed_rdesc", &rdesc).unwrap();
+        assert!(array.starts_with("static const __u8 fixed_rdesc[] = {\n\t0x05, 0x0d,"));
+        assert!(array.ends_with(
+            "\t0xc0,                          // End Collection                      106\n};\n"
+        ));
+    }
+
+    #[test]
+    fn test_diff() {
+        let source = include_str!("bpf/xppen-Artist24.bpf.c");
+        let fixed = rdesc_from_bpf_source(source, "fixed_rdesc");
+
+        assert!(diff(&fixed, &fixed)
+            .unwrap()
+            .iter()
+            .all(|l| matches!(l, DiffLine::Same(_))));
+
+        /* the fixup turns Eraser into Secondary Barrel Switch */
+        let mut original = fixed.clone();
+        original[17] = 0x45;
+        let changes: Vec<DiffLine> = diff(&original, &fixed)
+            .unwrap()
+            .into_iter()
+            .filter(|l| !matches!(l, DiffLine::Same(_)))
+            .collect();
+        assert!(changes.len() == 2);
+        assert!(matches!(&changes[0], DiffLine::Removed(i) if i.description == "Usage (Eraser)"));
+        assert!(changes[1].item().description == "Usage (Secondary Barrel Switch)");
+        assert!(changes[1].item().item.offset == 16);
+        assert!(changes[1].line().starts_with("+0x09, 0x5a,"));
+
+        /* an inserted item shifts the offsets of the following ones */
+        let mut original = fixed.clone();
+        original.drain(16..18);
+        let lines = diff(&original, &fixed).unwrap();
+        assert!(
+            lines
+                .iter()
+                .filter(|l| matches!(l, DiffLine::Added(_)))
+                .count()
+                == 1
+        );
+        assert!(!lines.iter().any(|l| matches!(l, DiffLine::Removed(_))));
+    }
+
+    #[test]
+    fn test_invalid_descriptors() {
+        /* Pop without Push */
+        assert!(ReportDescriptor::parse(&[0xb4]).is_err());
+        /* End Collection without Collection */
+        assert!(ReportDescriptor::parse(&[0xc0]).is_err());
+        /* Unterminated Collection */
+        assert!(ReportDescriptor::parse(&[0x09, 0x02, 0xa1, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_usage_ranges() {
+        let rdesc = [
+            0x05, 0x01, // Usage Page (Generic Desktop)
+            0x09, 0x02, // Usage (Mouse)
+            0xa1, 0x01, // Collection (Application)
+            0x1b, 0x00, 0x00, 0x00, 0x00, //  Usage Minimum (0)
+            0x2b, 0xff, 0xff, 0xff, 0x7f, //  Usage Maximum (2147483647)
+            0x81, 0x02, //  Input (Data,Var,Abs)
+            0xc0, // End Collection
+        ];
+
+        /* the range is kept as is, not expanded */
+        let rdesc = ReportDescriptor::parse(&rdesc).unwrap();
+        let field = &rdesc.reports[0].fields[0];
+        assert!(field.usage_ranges == vec![(0, 0x7fffffff)]);
+        assert!(field.has_usage(0x00010030));
+        assert!(!field.has_usage(0x80000000));
+        assert!(field.usages().take(2).collect::<Vec<u32>>() == vec![0, 1]);
+        assert!(rdesc.application_usages() == vec![0x00010002]);
+
+        /* Usage Maximum first is just as valid */
+        let rdesc = [
+            0x05, 0x09, // Usage Page (Button)
+            0x29, 0x03, // Usage Maximum (3)
+            0x19, 0x01, // Usage Minimum (1)
+            0x81, 0x02, // Input (Data,Var,Abs)
+            0x19, 0x04, // Usage Minimum (4), never paired
+            0x81, 0x03, // Input (Cnst,Var,Abs)
+        ];
+        let rdesc = ReportDescriptor::parse(&rdesc).unwrap();
+        let fields = &rdesc.reports[0].fields;
+        assert!(fields[0].usage_ranges == vec![(0x00090001, 0x00090003)]);
+        assert!(fields[1].usage_ranges.is_empty());
+    }
+
+    #[test]
+    fn test_xppen_artist24() {
+        let source = include_str!("bpf/xppen-Artist24.bpf.c");
+        let rdesc = rdesc_from_bpf_source(source, "fixed_rdesc");
+        assert!(rdesc.len() == 107);
+
+        let rdesc = ReportDescriptor::parse(&rdesc).unwrap();
+        assert!(rdesc.application_usages() == vec![0x000d0002]);
+
+        let application = &rdesc.collections[0];
+        assert!(application.fields.is_empty());
+        assert!(application.collections.len() == 1);
+        let stylus = &application.collections[0];
+        assert!(stylus.usage == Some(0x000d0020));
+        assert!(stylus.collection_type == 0x00);
+        assert!(stylus.fields.len() == 9);
+
+        assert!(rdesc.reports.len() == 1);
+        let report = &rdesc.reports[0];
+        assert!(report.kind == ReportKind::Input);
+        assert!(report.report_id == Some(7));
+        assert!(report.bits() == 80);
+
+        let buttons = &report.fields[0];
+        assert!(buttons.offset == 26);
+        assert!(buttons.usages().collect::<Vec<u32>>() == vec![0x000d0042, 0x000d0044, 0x000d005a]);
+        assert!(buttons.bit_offset == 8);
+        assert!(buttons.report_count == 3 && buttons.report_size == 1);
+        assert!(report.fields[1].is_constant());
+
+        /* the IN_RANGE bit of the BPF program */
+        let in_range = field_by_usage(report, 0x000d0032);
+        assert!(in_range.bit_offset == 8 + 5);
+
+        /* Push/Pop: X and Y are Generic Desktop, Tip Pressure is Digitizers */
+        let x = field_by_usage(report, 0x00010030);
+        assert!(x.bit_offset == 16 && x.report_size == 16);
+        assert!(x.logical_minimum == 0 && x.logical_maximum == 32767);
+        assert!(x.physical_maximum == 20720);
+        assert!(x.unit == 0x13 && x.unit_exponent == 0x0d);
+        let y = field_by_usage(report, 0x00010031);
+        assert!(y.bit_offset == 32 && y.physical_maximum == 11665);
+        let pressure = field_by_usage(report, 0x000d0030);
+        assert!(pressure.bit_offset == 48);
+        assert!(pressure.logical_maximum == 8191);
+        assert!(pressure.physical_maximum == 0 && pressure.unit == 0);
+
+        let x_tilt = field_by_usage(report, 0x000d003d);
+        assert!(x_tilt.bit_offset == 64 && x_tilt.report_size == 8);
+        assert!(x_tilt.logical_minimum == -127 && x_tilt.logical_maximum == 127);
+        assert!(field_by_usage(report, 0x000d003e).bit_offset == 72);
+    }
+
+    #[test]
+    fn test_xppen_artist_pro16_gen2() {
+        let source = include_str!("bpf/xppen-ArtistPro16Gen2.bpf.c");
+        let rdesc = rdesc_from_bpf_source(source, "fixed_rdesc");
+        assert!(rdesc.len() == 111);
+
+        let rdesc = ReportDescriptor::parse(&rdesc).unwrap();
+        assert!(rdesc.application_usages() == vec![0x000d0002]);
+        assert!(rdesc.reports.len() == 1);
+
+        /* the device_event programs read 10 bytes */
+        let report = &rdesc.reports[0];
+        assert!(report.bits() == 80);
+
+        /* Eraser was added over a padding bit */
+        let buttons = &report.fields[0];
+        assert!(buttons.report_count == 5);
+        assert!(buttons.usages().count() == 5);
+        assert!(buttons.usages().nth(4) == Some(0x000d0045));
+        assert!(field_by_usage(report, 0x000d0032).bit_offset == 8 + 5);
+
+        /* the offsets used by xppen_16_fix_angle_offset */
+        assert!(field_by_usage(report, 0x00010030).bit_offset == 2 * 8);
+        assert!(field_by_usage(report, 0x00010031).bit_offset == 4 * 8);
+        assert!(field_by_usage(report, 0x000d003d).bit_offset == 8 * 8);
+        assert!(field_by_usage(report, 0x000d003e).bit_offset == 9 * 8);
+    }
+
+    #[test]
+    fn test_xbox_elite_2_fixup() {
+        let source = include_str!("bpf/XBox_Elite_2.bpf.c");
+        let original = rdesc_from_bpf_source(source, "rdesc_assign_selection");
+        let fixed = rdesc_from_bpf_source(source, "fixed_rdesc_assign_selection");
+        assert!(original.len() == fixed.len());
+
+        /* extracts only, they are not balanced on their own */
+        let original = items(&original).unwrap();
+        let fixed = items(&fixed).unwrap();
+        assert!(original.iter().map(|i| i.raw.len()).sum::<usize>() == 38);
+        assert!(fixed
+            .iter()
+            .any(|i| i.item_type == ItemType::Main && i.tag == END_COLLECTION));
+        assert!(fixed
+            .iter()
+            .filter(|i| i.item_type == ItemType::Local && i.tag == USAGE_MINIMUM)
+            .all(|i| i.value() == 0x15));
+    }
+}